@@ -26,7 +26,7 @@ impl ring::aead::NonceSequence for OneNonceSequence {
 /// Length of an AES-256-GCM nonce in bytes.
 pub const NONCE_LEN: usize = 12;
 
-/// Length of a PBKDF2 salt in bytes.
+/// Length of a password KDF salt in bytes.
 pub const SALT_LEN: usize = 64;
 
 pub const SALT_PREFIX: &[u8] = b"salt:";
@@ -126,17 +126,27 @@ pub fn new_salt() -> crate::Result<Vec<u8>> {
 // ---------------------------------------------------------------------------
 
 pub fn hmac_sign(s: &str, key: &[u8]) -> String {
+    hmac_sign_bytes(s.as_bytes(), key)
+}
+
+pub fn hmac_verify(text: &str, sig: &str, key: &[u8]) -> bool {
+    hmac_verify_bytes(text.as_bytes(), sig, key)
+}
+
+/// HMAC-SHA256 hex signature of arbitrary `bytes`.
+pub fn hmac_sign_bytes(bytes: &[u8], key: &[u8]) -> String {
     let s_key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key);
-    let tag = ring::hmac::sign(&s_key, s.as_bytes());
+    let tag = ring::hmac::sign(&s_key, bytes);
     hex::encode(tag)
 }
 
-pub fn hmac_verify(text: &str, sig: &str, key: &[u8]) -> bool {
+/// Verify a hex signature produced by [`hmac_sign_bytes`].
+pub fn hmac_verify_bytes(bytes: &[u8], sig: &str, key: &[u8]) -> bool {
     let Ok(sig) = hex::decode(sig) else {
         return false;
     };
     let s_key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key);
-    ring::hmac::verify(&s_key, text.as_bytes(), &sig).is_ok()
+    ring::hmac::verify(&s_key, bytes, &sig).is_ok()
 }
 
 /// Return the SHA-256 hash of `bytes`.
@@ -148,17 +158,149 @@ pub fn sha256(bytes: &[u8]) -> Vec<u8> {
 // Key derivation
 // ---------------------------------------------------------------------------
 
-/// Stretch `pw` into a 32-byte AES-256-GCM key using PBKDF2-HMAC-SHA512.
+/// A password key derivation function and its cost parameters.
+///
+/// Stored next to what it encrypted, so the cost of new keys can be raised
+/// without breaking old ciphertexts.  Written in the `"alg[:param=n,...]"`
+/// format of [`Kdf::from_str`](std::str::FromStr):
+/// - `"argon2id:m=19456,t=2,p=1"` — memory in KiB, iterations, parallelism
+/// - `"pbkdf2-sha512:i=100000"` — iterations
+///
+/// Missing parameters take the defaults of [`Kdf::ARGON2ID`] and
+/// [`Kdf::PBKDF2_LEGACY`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kdf {
+    Pbkdf2Sha512 {
+        iterations: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl Kdf {
+    /// What [`derive_encryption_key`] and the [`Encrypted::Pw`] envelope
+    /// use, and what blobs that record no KDF were encrypted with.
+    pub const PBKDF2_LEGACY: Self = Self::Pbkdf2Sha512 {
+        iterations: 100_000,
+    };
+
+    /// Argon2id with the OWASP-recommended minimum cost.
+    pub const ARGON2ID: Self = Self::Argon2id {
+        memory_kib: 19 * 1024,
+        iterations: 2,
+        parallelism: 1,
+    };
+
+    /// Stretch `pw` into a 32-byte AES-256-GCM key.  Fails if the
+    /// parameters are out of range for the algorithm.
+    pub fn derive(&self, pw: &[u8], salt: &[u8]) -> crate::Result<[u8; 32]> {
+        let mut out = [0u8; 32];
+        match *self {
+            Self::Pbkdf2Sha512 { iterations } => {
+                let iterations =
+                    NonZeroU32::new(iterations).ok_or("kdf: PBKDF2 iterations must be non-zero")?;
+                pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA512, iterations, salt, pw, &mut out);
+            }
+            Self::Argon2id { .. } => {
+                argon2::Argon2::new(
+                    argon2::Algorithm::Argon2id,
+                    argon2::Version::V0x13,
+                    self.argon2_params()?,
+                )
+                .hash_password_into(pw, salt, &mut out)
+                .map_err(|e| format!("kdf: Argon2id failed: {e}"))?;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Check the parameters are in range, without deriving anything.
+    pub fn validate(&self) -> crate::Result<()> {
+        match *self {
+            Self::Pbkdf2Sha512 { iterations: 0 } => {
+                Err("kdf: PBKDF2 iterations must be non-zero".into())
+            }
+            Self::Pbkdf2Sha512 { .. } => Ok(()),
+            Self::Argon2id { .. } => self.argon2_params().map(drop),
+        }
+    }
+
+    fn argon2_params(&self) -> crate::Result<argon2::Params> {
+        let Self::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        } = *self
+        else {
+            return Err("kdf: not Argon2id".into());
+        };
+        argon2::Params::new(memory_kib, iterations, parallelism, Some(32))
+            .map_err(|e| format!("kdf: invalid Argon2id parameters: {e}").into())
+    }
+}
+
+impl Default for Kdf {
+    fn default() -> Self {
+        Self::ARGON2ID
+    }
+}
+
+impl std::fmt::Display for Kdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pbkdf2Sha512 { iterations } => write!(f, "pbkdf2-sha512:i={iterations}"),
+            Self::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => write!(f, "argon2id:m={memory_kib},t={iterations},p={parallelism}"),
+        }
+    }
+}
+
+impl std::str::FromStr for Kdf {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        let (alg, params) = s.split_once(':').unwrap_or((s, ""));
+        let mut kdf = match alg.trim() {
+            "argon2id" => Self::ARGON2ID,
+            "pbkdf2-sha512" => Self::PBKDF2_LEGACY,
+            other => return Err(format!("kdf: unknown algorithm {other:?}").into()),
+        };
+        for param in params.split(',').filter(|p| !p.trim().is_empty()) {
+            let (name, value) = param
+                .split_once('=')
+                .ok_or_else(|| format!("kdf: expected name=value, got {param:?}"))?;
+            let value: u32 = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("kdf: invalid value for {name:?}"))?;
+            match (&mut kdf, name.trim()) {
+                (Self::Pbkdf2Sha512 { iterations }, "i")
+                | (Self::Argon2id { iterations, .. }, "t") => *iterations = value,
+                (Self::Argon2id { memory_kib, .. }, "m") => *memory_kib = value,
+                (Self::Argon2id { parallelism, .. }, "p") => *parallelism = value,
+                (_, name) => {
+                    return Err(format!("kdf: unknown parameter {name:?} for {alg}").into())
+                }
+            }
+        }
+        kdf.validate()?;
+        Ok(kdf)
+    }
+}
+
+/// Stretch `pw` into a 32-byte AES-256-GCM key using
+/// [`Kdf::PBKDF2_LEGACY`].
 pub fn derive_encryption_key(pw: &[u8], salt: &[u8]) -> [u8; 32] {
-    let mut out = [0u8; 32];
-    pbkdf2::derive(
-        pbkdf2::PBKDF2_HMAC_SHA512,
-        NonZeroU32::new(100_000).unwrap(),
-        salt,
-        pw,
-        &mut out,
-    );
-    out
+    Kdf::PBKDF2_LEGACY
+        .derive(pw, salt)
+        .expect("valid PBKDF2 parameters")
 }
 
 // ---------------------------------------------------------------------------
@@ -202,7 +344,9 @@ fn aes_open(ciphertext: &[u8], nonce: &[u8], key: &[u8], aad: &[u8]) -> crate::R
 /// - [`Encrypted::Key`] — encrypted with a raw 32-byte key (no KDF).
 ///   Call [`encode`](Encrypted::encode) to get
 ///   `base64(KEY_ID_PREFIX ‖ id ‖ \x00 ‖ nonce ‖ ciphertext)`.
-/// - [`Encrypted::Pw`] — encrypted with a password (PBKDF2 KDF applied).
+/// - [`Encrypted::Pw`] — encrypted with a password.  The envelope doesn't
+///   record the KDF: [`encrypt_with_pw`] uses [`Kdf::PBKDF2_LEGACY`], and
+///   [`encrypt_with_pw_kdf_aad`] leaves it to the caller to store.
 ///   Call [`encode`](Encrypted::encode) to get
 ///   `base64(SALT_PREFIX ‖ salt ‖ nonce ‖ ciphertext)`.
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
@@ -389,11 +533,11 @@ pub fn decrypt_with_aad(
 }
 
 // ---------------------------------------------------------------------------
-// Password-based encrypt / decrypt (PBKDF2 KDF applied automatically)
+// Password-based encrypt / decrypt (KDF applied automatically)
 // ---------------------------------------------------------------------------
 
 /// Encrypt `plaintext` with `password` using a fresh salt and automatic
-/// PBKDF2-HMAC-SHA512 key derivation.
+/// [`Kdf::PBKDF2_LEGACY`] key derivation.
 pub fn encrypt_with_pw(plaintext: &[u8], password: &[u8]) -> crate::Result<Encrypted> {
     encrypt_with_pw_aad(plaintext, password, &[])
 }
@@ -408,10 +552,26 @@ pub fn encrypt_with_pw_aad(
     plaintext: &[u8],
     password: &[u8],
     aad: &[u8],
+) -> crate::Result<Encrypted> {
+    encrypt_with_pw_kdf_aad(plaintext, password, &Kdf::PBKDF2_LEGACY, aad)
+}
+
+/// Decrypt an [`Encrypted::Pw`] value with `password` and additional authenticated data.
+pub fn decrypt_with_pw_aad(enc: &Encrypted, password: &[u8], aad: &[u8]) -> crate::Result<Vec<u8>> {
+    decrypt_with_pw_kdf_aad(enc, password, &Kdf::PBKDF2_LEGACY, aad)
+}
+
+/// [`encrypt_with_pw_aad`] with the key derived by `kdf`, which the caller
+/// must record to decrypt with.
+pub fn encrypt_with_pw_kdf_aad(
+    plaintext: &[u8],
+    password: &[u8],
+    kdf: &Kdf,
+    aad: &[u8],
 ) -> crate::Result<Encrypted> {
     let nonce = new_nonce()?;
     let salt = new_salt()?;
-    let key = derive_encryption_key(password, &salt);
+    let key = kdf.derive(password, &salt)?;
     let ciphertext = aes_seal(plaintext, &nonce, &key, aad)?;
     Ok(Encrypted::Pw {
         salt,
@@ -420,15 +580,20 @@ pub fn encrypt_with_pw_aad(
     })
 }
 
-/// Decrypt an [`Encrypted::Pw`] value with `password` and additional authenticated data.
-pub fn decrypt_with_pw_aad(enc: &Encrypted, password: &[u8], aad: &[u8]) -> crate::Result<Vec<u8>> {
+/// Decrypt an [`Encrypted::Pw`] value from [`encrypt_with_pw_kdf_aad`].
+pub fn decrypt_with_pw_kdf_aad(
+    enc: &Encrypted,
+    password: &[u8],
+    kdf: &Kdf,
+    aad: &[u8],
+) -> crate::Result<Vec<u8>> {
     match enc {
         Encrypted::Pw {
             salt,
             nonce,
             ciphertext,
         } => {
-            let key = derive_encryption_key(password, salt);
+            let key = kdf.derive(password, salt)?;
             aes_open(ciphertext, nonce, &key, aad)
         }
         Encrypted::Key { .. } => Err("decrypt_with_pw: expected Pw variant, got Key".into()),
@@ -539,6 +704,22 @@ mod tests {
         assert!(!hmac_verify("anything", "not-hex!", b"key"));
     }
 
+    #[test]
+    fn hmac_bytes_matches_str_for_utf8() {
+        let key = b"key";
+        assert_eq!(hmac_sign_bytes(b"same", key), hmac_sign("same", key));
+    }
+
+    #[test]
+    fn hmac_bytes_verify_roundtrip_non_utf8() {
+        let key = b"bytes-key";
+        let bytes = [0xff, 0xfe, 0x00, 0x80];
+        let sig = hmac_sign_bytes(&bytes, key);
+        assert!(hmac_verify_bytes(&bytes, &sig, key));
+        assert!(!hmac_verify_bytes(&[0xff, 0xfe, 0x00, 0x81], &sig, key));
+        assert_ne!(sig, hmac_sign("", key));
+    }
+
     // ---------------------------------------------------------------------------
     // derive_encryption_key
     // ---------------------------------------------------------------------------
@@ -574,6 +755,74 @@ mod tests {
         assert_ne!(k1, k2);
     }
 
+    /// Cheap enough for unoptimised test builds.
+    const TEST_ARGON2ID: Kdf = Kdf::Argon2id {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn legacy_kdf_matches_derive_encryption_key() {
+        let salt = b"0123456789abcdef";
+        assert_eq!(
+            Kdf::PBKDF2_LEGACY.derive(b"pw", salt).unwrap(),
+            derive_encryption_key(b"pw", salt)
+        );
+    }
+
+    #[test]
+    fn argon2id_differs_from_pbkdf2_and_by_params() {
+        let salt = b"0123456789abcdef";
+        let k1 = TEST_ARGON2ID.derive(b"pw", salt).unwrap();
+        assert_eq!(k1, TEST_ARGON2ID.derive(b"pw", salt).unwrap());
+        assert_ne!(k1, derive_encryption_key(b"pw", salt));
+        let costlier = Kdf::Argon2id {
+            memory_kib: 64,
+            iterations: 2,
+            parallelism: 1,
+        };
+        assert_ne!(k1, costlier.derive(b"pw", salt).unwrap());
+    }
+
+    #[test]
+    fn kdf_rejects_bad_params() {
+        let too_little_memory = Kdf::Argon2id {
+            memory_kib: 1,
+            iterations: 1,
+            parallelism: 1,
+        };
+        assert!(too_little_memory
+            .derive(b"pw", b"0123456789abcdef")
+            .is_err());
+        let no_iterations = Kdf::Pbkdf2Sha512 { iterations: 0 };
+        assert!(no_iterations.derive(b"pw", b"0123456789abcdef").is_err());
+    }
+
+    #[test]
+    fn kdf_parse_and_display() {
+        let kdf: Kdf = "argon2id:m=64,t=1,p=1".parse().unwrap();
+        assert_eq!(kdf, TEST_ARGON2ID);
+        assert_eq!(kdf.to_string(), "argon2id:m=64,t=1,p=1");
+        let kdf: Kdf = "pbkdf2-sha512".parse().unwrap();
+        assert_eq!(kdf, Kdf::PBKDF2_LEGACY);
+        assert_eq!(kdf.to_string().parse::<Kdf>().unwrap(), kdf);
+        assert!("scrypt".parse::<Kdf>().is_err());
+        assert!("argon2id:i=5".parse::<Kdf>().is_err());
+        assert!("argon2id:m=1".parse::<Kdf>().is_err());
+    }
+
+    #[test]
+    fn pw_kdf_roundtrip_needs_the_same_kdf() {
+        let enc = encrypt_with_pw_kdf_aad(b"secret", b"pw", &TEST_ARGON2ID, b"aad").unwrap();
+        assert_eq!(
+            decrypt_with_pw_kdf_aad(&enc, b"pw", &TEST_ARGON2ID, b"aad").unwrap(),
+            b"secret"
+        );
+        assert!(decrypt_with_pw_kdf_aad(&enc, b"pw", &Kdf::PBKDF2_LEGACY, b"aad").is_err());
+        assert!(decrypt_with_pw_kdf_aad(&enc, b"nope", &TEST_ARGON2ID, b"aad").is_err());
+    }
+
     // ---------------------------------------------------------------------------
     // Key / KeyRef parsing
     // ---------------------------------------------------------------------------
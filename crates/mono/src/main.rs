@@ -35,6 +35,16 @@ async fn main() {
         .await
         .expect("failed to initialize paste state");
 
+    let admin_addr = paste_state.config.admin_addr.clone();
+    if !admin_addr.is_empty() {
+        let admin = paste::service::admin_router().with_state(paste_state.clone());
+        let listener = tokio::net::TcpListener::bind(&admin_addr)
+            .await
+            .unwrap_or_else(|e| panic!("failed to bind to {}: {}", admin_addr, e));
+        tracing::info!("serving paste admin endpoints on {}", admin_addr);
+        tokio::spawn(async move { axum::serve(listener, admin).await.unwrap() });
+    }
+
     let app = app(spot_state, paste_state);
     let addr = CONFIG.get_host_port();
     let listener = tokio::net::TcpListener::bind(&addr)
@@ -5,23 +5,91 @@
 
 VIEW_BASE_URL = "/paste/";
 
+/** Client-side encryption
+ * - "encrypt in browser" pastes are AES-GCM encrypted here with a fresh key
+ *   that only ever lives in the URL fragment; the server stores ciphertext
+ *   plus the params sent in the x-paste-client-encryption header.
+ */
+function b64urlEncode(bytes) {
+    var bin = "";
+    bytes = new Uint8Array(bytes);
+    for (var i = 0; i < bytes.length; i++) { bin += String.fromCharCode(bytes[i]); }
+    return btoa(bin).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+}
+
+function b64Decode(str) {
+    str = str.replace(/-/g, "+").replace(/_/g, "/");
+    while (str.length % 4) { str += "="; }
+    var bin = atob(str);
+    var bytes = new Uint8Array(bin.length);
+    for (var i = 0; i < bin.length; i++) { bytes[i] = bin.charCodeAt(i); }
+    return bytes;
+}
+
+function clientEncrypt(text) {
+    var iv = crypto.getRandomValues(new Uint8Array(12));
+    return crypto.subtle.generateKey({name: "AES-GCM", length: 256}, true, ["encrypt"]).then(function(key) {
+        var data = new TextEncoder().encode(text);
+        return Promise.all([
+            crypto.subtle.encrypt({name: "AES-GCM", iv: iv}, key, data),
+            crypto.subtle.exportKey("raw", key),
+        ]);
+    }).then(function(res) {
+        return {
+            ciphertext: res[0],
+            key: b64urlEncode(res[1]),
+            params: {alg: "AES-GCM", iv: b64urlEncode(iv)},
+        };
+    });
+}
+
+function clientDecrypt(ciphertextB64, params, keyB64) {
+    if (params.alg !== "AES-GCM") {
+        return Promise.reject(new Error("unsupported algorithm " + params.alg));
+    }
+    return crypto.subtle.importKey("raw", b64Decode(keyB64), "AES-GCM", false, ["decrypt"]).then(function(key) {
+        return crypto.subtle.decrypt({name: "AES-GCM", iv: b64Decode(params.iv)}, key, b64Decode(ciphertextB64));
+    }).then(function(plain) {
+        return new TextDecoder().decode(plain);
+    });
+}
+
 document.addEventListener("DOMContentLoaded", function() {
     var save   = document.getElementById("save-paste");     // save-paste button/element
     var edit   = document.getElementById("edit-paste");     // edit-paste button/element
+    var fork   = document.getElementById("fork-paste");     // fork-paste button/element
 
     var pasteType = document.getElementById("paste-type");          // ace-editor mode (syntax)
     var typeSelector = document.getElementById("type-selector");    // select ace-editor mode
     var encryptionKeyInput = document.getElementById("encryption-key");    // select encryption-key password
+    var burnLabel = document.getElementById("burn-label");                 // burn-after-reading toggle
+    var burnAfterReading = document.getElementById("burn-after-reading");
+    var maxViewsInput = document.getElementById("max-views");               // optional view limit
     var pasteId = document.getElementById("paste-id");              // existing paste-id
     var copyLink = document.getElementById("copy-link");                   // share button
     var copyCode = document.getElementById("copy-code");                   // share button
     var encryptionKeyRequired = !!document.getElementById("encryption-key-required");
+    var binaryContent = !!document.getElementById("binary-content");    // non-text paste, not editable
     var decryptionKeyInput = document.getElementById("decryption-key");   // decryption pass
     var decryptPaste = document.getElementById("decrypt-paste");     // decrypt button
     var editorElem = document.getElementById("editor");
+    var jsonUrl = document.getElementById("json-url");                     // set when viewing a specific revision
+    var clientEncryptInput = document.getElementById("client-encrypt");   // encrypt in browser toggle
+    var clientEncryptLabel = document.getElementById("client-encrypt-label");
+    var clientCiphertext = document.getElementById("client-ciphertext");  // set for client-encrypted pastes
+    var clientParams = document.getElementById("client-params");
+    var clientKeyInput = document.getElementById("client-key");           // key, when the link lacks it
+    var clientDecryptButton = document.getElementById("client-decrypt");
 
+    if (encryptionKeyRequired || binaryContent) {
+        edit.style.display = "none";
+    }
     if (encryptionKeyRequired) {
+        fork.style.display = "none";
+    }
+    if (clientCiphertext) {
         edit.style.display = "none";
+        fork.style.display = "none";
     }
 
     // initialize editor with theme
@@ -44,7 +112,7 @@ document.addEventListener("DOMContentLoaded", function() {
         var _pasteKey = pasteId.innerText;
 
         var http = new XMLHttpRequest();
-        var url  = "/paste/json/"+_pasteKey;
+        var url  = jsonUrl ? jsonUrl.value : "/paste/json/"+_pasteKey;
         http.open("GET", url, true);
         http.setRequestHeader("x-paste-encryption-key", _decKey);
         http.onreadystatechange = function() {
@@ -63,6 +131,7 @@ document.addEventListener("DOMContentLoaded", function() {
                 decryptionKeyInput.style.display = "none";
                 decryptPaste.style.display = "none";
                 edit.style.display = "";
+                fork.style.display = "";
                 editorElem.style.top = "70";
 
                 for (var i = 0, len = typeSelector.length; i < len; i++) {
@@ -92,6 +161,42 @@ document.addEventListener("DOMContentLoaded", function() {
         });
     }
 
+    /** Decrypt client-encrypted content
+     * - with the key from the URL fragment, or the key input when it's missing
+     */
+    function doClientDecrypt(keyB64) {
+        var params = JSON.parse(clientParams.value);
+        clientDecrypt(clientCiphertext.value, params, keyB64).then(function(content) {
+            editor.setValue(content, -1);
+            clientKeyInput.style.display = "none";
+            clientDecryptButton.style.display = "none";
+            edit.style.display = "";
+            fork.style.display = "";
+            editorElem.style.top = "70";
+            if (!window.location.hash) {
+                history.replaceState(null, "", "#" + keyB64);
+            }
+        }).catch(function() {
+            alert("Error decrypting paste.");
+        });
+    }
+    if (clientCiphertext) {
+        if (window.location.hash.length > 1) {
+            doClientDecrypt(window.location.hash.substring(1));
+        } else {
+            clientKeyInput.style.display = "";
+            clientDecryptButton.style.display = "";
+        }
+        clientKeyInput.addEventListener("keyup", function(ev) {
+            if (ev.key === "Enter") {
+                doClientDecrypt(clientKeyInput.value.replace(/^.*#/, ""));
+            }
+        });
+        clientDecryptButton.addEventListener("click", function() {
+            doClientDecrypt(clientKeyInput.value.replace(/^.*#/, ""));
+        });
+    }
+
     /** Save content
      * - When the save button is present (which it should always be, might just be hidden),
      *   add a listener to post current content and redirect to a viewable link
@@ -102,34 +207,63 @@ document.addEventListener("DOMContentLoaded", function() {
         didSave = true;
         var content = editor.getValue();
         var contentType = typeSelector.value;
-        if (!contentType) { contentType = "text" }
+        if (!contentType) { contentType = "auto" }
         var encryptionKey = encryptionKeyInput.value;
         var hasKey = !(encryptionKey === "" || encryptionKey === null || encryptionKey === undefined);
 
+        if (clientEncryptInput.checked) {
+            if (hasKey) {
+                didSave = false;
+                alert("Choose either an encryption key or encrypt in browser.");
+                return;
+            }
+            clientEncrypt(content).then(function(sealed) {
+                postPaste(contentType, sealed.ciphertext, "application/octet-stream", {
+                    "x-paste-client-encryption": JSON.stringify(sealed.params),
+                }, "#" + sealed.key);
+            }).catch(function() {
+                didSave = false;
+                alert("Error encrypting paste.");
+            });
+            return;
+        }
+        var extraHeaders = {};
+        if (hasKey) {
+            extraHeaders["x-paste-encryption-key"] = encryptionKey;
+        }
+        postPaste(contentType, content, "text/plain", extraHeaders, "");
+    }
+    function postPaste(contentType, body, mimeType, extraHeaders, fragment) {
         var http = new XMLHttpRequest();
         var url  = "/paste/new?type="+contentType;
+        if (burnAfterReading.checked) {
+            url += "&burn_after_reading=true";
+        }
+        if (maxViewsInput.value) {
+            url += "&max_views="+encodeURIComponent(maxViewsInput.value);
+        }
         http.open("POST", url, true);
-        http.setRequestHeader("Content-Type", "text/plain");
-        if (hasKey) {
-            http.setRequestHeader("x-paste-encryption-key", encryptionKey);
+        http.setRequestHeader("Content-Type", mimeType);
+        for (var name in extraHeaders) {
+            http.setRequestHeader(name, extraHeaders[name]);
         }
         http.onreadystatechange = function() {
             if (http.readyState !== XMLHttpRequest.DONE) { return; }
-            if (http.status != 200) {
+            if (http.status != 201) {
                 didSave = false;
                 alert("Error posting paste.");
                 return;
             }
             var resp = JSON.parse(http.responseText);
             if (resp.key) {
-                window.location.href = VIEW_BASE_URL+resp.key;
+                window.location.href = VIEW_BASE_URL+resp.key+fragment;
             }
             else {
                 didSave = false;
                 alert("Error posting paste.");
             }
         }
-        http.send(content);
+        http.send(body);
     }
     if (encryptionKeyInput) {
         encryptionKeyInput.addEventListener("keyup", function(ev) {
@@ -144,6 +278,39 @@ document.addEventListener("DOMContentLoaded", function() {
         });
     }
 
+    /** Fork existing content
+     * - When the fork button is present, copy the paste server-side (re-using
+     *   the decryption key, if any) and redirect to the fork
+     */
+    var didFork = false;
+    function doFork() {
+        if (didFork) { return; }
+        didFork = true;
+        var http = new XMLHttpRequest();
+        http.open("POST", "/paste/"+pasteId.innerText.trim()+"/fork", true);
+        if (decryptionKeyInput && decryptionKeyInput.value) {
+            http.setRequestHeader("x-paste-encryption-key", decryptionKeyInput.value);
+        }
+        http.onreadystatechange = function() {
+            if (http.readyState !== XMLHttpRequest.DONE) { return; }
+            var resp = http.status == 201 ? JSON.parse(http.responseText) : {};
+            if (resp.key) {
+                // a client-encrypted fork opens with the same key
+                window.location.href = VIEW_BASE_URL+resp.key+(clientCiphertext ? window.location.hash : "");
+            }
+            else {
+                didFork = false;
+                alert("Error forking paste.");
+            }
+        }
+        http.send();
+    }
+    if (fork) {
+        fork.addEventListener("click", function(){
+            doFork();
+        });
+    }
+
     /** Edit existing content
      * - When the edit button is present:
      *   - make editor readonly
@@ -154,6 +321,7 @@ document.addEventListener("DOMContentLoaded", function() {
         editor.setReadOnly(true);
         edit.addEventListener("click", function(){
             edit.style.display = "none";
+            fork.style.display = "none";
             save.style.display = "";
             var key = document.getElementById("paste-id");
             key.innerText = '';
@@ -171,6 +339,12 @@ document.addEventListener("DOMContentLoaded", function() {
 
             encryptionKeyInput.style.display = "";
             encryptionKeyInput.value = "";
+            burnLabel.style.display = "";
+            burnAfterReading.checked = false;
+            maxViewsInput.style.display = "";
+            maxViewsInput.value = "";
+            clientEncryptLabel.style.display = "";
+            clientEncryptInput.checked = !!clientCiphertext;
 
             copyLink.style.cssText = "display: none;";
             copyCode.style.cssText = "display: none;";
@@ -183,7 +357,8 @@ document.addEventListener("DOMContentLoaded", function() {
         var copyLinkText = copyLink.innerText;
         var copyCodeText = copyCode.innerText;
         copyLink.addEventListener("click", function() {
-            navigator.clipboard.writeText(window.location.protocol + '//' + window.location.hostname + VIEW_BASE_URL + pasteId.innerText.trim());
+            var fragment = clientCiphertext ? window.location.hash : "";
+            navigator.clipboard.writeText(window.location.protocol + '//' + window.location.hostname + VIEW_BASE_URL + pasteId.innerText.trim() + fragment);
             copyLink.innerText = copyLinkText + " ✓";
             copyCode.innerText = copyCodeText;
         });
@@ -0,0 +1,227 @@
+//! Optional accounts for paste creators.
+//!
+//! Accounts exist so API clients — CI bots in particular — can find and
+//! clean up what they created.  An account is identified by bearer API
+//! tokens, of which only the HMAC is stored.  Pastes created with a token
+//! record the account as their `owner_id`; anonymous creation is unchanged.
+
+use chrono::{DateTime, Utc};
+use rand::distr::Alphanumeric;
+use rand::RngExt;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::models::READABLE;
+use crate::Config;
+
+/// Prefix of every API token, so they're recognisable in logs and secret
+/// scanners.
+pub const API_TOKEN_PREFIX: &str = "pst_";
+
+/// Random characters after [`API_TOKEN_PREFIX`].
+const API_TOKEN_CHARS: usize = 40;
+
+/// Longest account or token name.
+pub const MAX_NAME_LEN: usize = 100;
+
+/// Most pastes returned by one [`User::pastes`] call.
+pub const MAX_PASTES_PAGE: i64 = 1000;
+
+fn new_api_token() -> String {
+    let random: String = rand::rng()
+        .sample_iter(Alphanumeric)
+        .take(API_TOKEN_CHARS)
+        .map(char::from)
+        .collect();
+    format!("{API_TOKEN_PREFIX}{random}")
+}
+
+/// HMAC of an API token, as stored in `paste_api_tokens`.
+fn hash_api_token(token: &str, config: &Config) -> String {
+    common::crypto::hmac_sign(token, config.signing_key.as_bytes())
+}
+
+/// An account name or token name: non-empty, bounded and printable.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.trim().is_empty() && name.len() <= MAX_NAME_LEN && !name.chars().any(char::is_control)
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct User {
+    pub id: i32,
+    pub name: String,
+    pub date_created: DateTime<Utc>,
+}
+
+/// A paste in its owner's listing.  Content isn't included; reading it
+/// would count as a view.
+#[derive(Debug, FromRow, Serialize)]
+pub struct OwnedPaste {
+    pub key: String,
+    #[serde(rename = "type")]
+    pub content_type: String,
+    pub date_created: DateTime<Utc>,
+    pub exp_date: Option<DateTime<Utc>>,
+    pub view_count: i32,
+    pub max_views: Option<i32>,
+    pub burn_after_reading: bool,
+}
+
+impl User {
+    /// Create an account along with its first API token, which is returned
+    /// and can't be recovered later.
+    pub async fn create(
+        pool: &common::db::DbPool,
+        config: &Config,
+        name: &str,
+    ) -> anyhow::Result<(User, String)> {
+        let mut tx = pool.begin().await?;
+        let user: User = sqlx::query_as(
+            "INSERT INTO paste_users (name) VALUES ($1) RETURNING id, name, date_created",
+        )
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await?;
+        let token = new_api_token();
+        sqlx::query("INSERT INTO paste_api_tokens (hash, user_id) VALUES ($1, $2)")
+            .bind(hash_api_token(&token, config))
+            .bind(user.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok((user, token))
+    }
+
+    /// The account `token` belongs to, if any.  Records the token as used.
+    pub async fn authenticate(
+        pool: &common::db::DbPool,
+        config: &Config,
+        token: &str,
+    ) -> anyhow::Result<Option<User>> {
+        if !token.starts_with(API_TOKEN_PREFIX) {
+            return Ok(None);
+        }
+        let user = sqlx::query_as(
+            "UPDATE paste_api_tokens t SET date_used = NOW()
+             FROM paste_users u
+             WHERE t.hash = $1 AND u.id = t.user_id
+             RETURNING u.id, u.name, u.date_created",
+        )
+        .bind(hash_api_token(token, config))
+        .fetch_optional(pool)
+        .await?;
+        Ok(user)
+    }
+
+    /// Issue another API token for this account.
+    pub async fn new_token(
+        &self,
+        pool: &common::db::DbPool,
+        config: &Config,
+        name: Option<&str>,
+    ) -> anyhow::Result<String> {
+        let token = new_api_token();
+        sqlx::query("INSERT INTO paste_api_tokens (hash, user_id, name) VALUES ($1, $2, $3)")
+            .bind(hash_api_token(&token, config))
+            .bind(self.id)
+            .bind(name)
+            .execute(pool)
+            .await?;
+        Ok(token)
+    }
+
+    /// Revoke one of this account's tokens.  `false` if it isn't one.
+    pub async fn revoke_token(
+        &self,
+        pool: &common::db::DbPool,
+        config: &Config,
+        token: &str,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM paste_api_tokens WHERE hash = $1 AND user_id = $2")
+            .bind(hash_api_token(token, config))
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// This account's readable pastes, newest first, and the total count.
+    pub async fn pastes(
+        &self,
+        pool: &common::db::DbPool,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<(Vec<OwnedPaste>, i64)> {
+        let visible =
+            format!("owner_id = $1 AND (exp_date IS NULL OR exp_date > NOW()) AND {READABLE}");
+        let pastes = sqlx::query_as(&format!(
+            "SELECT key, content_type, date_created, exp_date, view_count, max_views, burn_after_reading
+             FROM pastes WHERE {visible}
+             ORDER BY date_created DESC, id DESC
+             LIMIT $2 OFFSET $3"
+        ))
+        .bind(self.id)
+        .bind(limit.clamp(1, MAX_PASTES_PAGE))
+        .bind(offset.max(0))
+        .fetch_all(pool)
+        .await?;
+        let total = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM pastes WHERE {visible}"))
+            .bind(self.id)
+            .fetch_one(pool)
+            .await?;
+        Ok((pastes, total))
+    }
+
+    /// Delete this account's pastes: those in `keys`, or all of them when
+    /// `keys` is `None`.  Keys of other accounts' pastes are ignored.
+    ///
+    /// The pastes are expired at once, so they stop being readable, and
+    /// queued for deletion in the same statement, see [`crate::deletion`].
+    /// Returns the keys deleted.
+    pub async fn delete_pastes(
+        &self,
+        pool: &common::db::DbPool,
+        keys: Option<&[String]>,
+    ) -> anyhow::Result<Vec<String>> {
+        let deleted = sqlx::query_scalar(
+            "WITH doomed AS (
+                 UPDATE pastes SET exp_date = NOW(), date_queued = NOW()
+                 WHERE owner_id = $1 AND ($2::text[] IS NULL OR key = ANY($2))
+                     AND (exp_date IS NULL OR exp_date > NOW())
+                 RETURNING key, id, storage_uri, date_created
+             ), queued AS (
+                 INSERT INTO paste_deletion_jobs (paste_id, storage_uri, paste_created)
+                 SELECT id, storage_uri, date_created FROM doomed
+                 ON CONFLICT (paste_id) DO NOTHING
+             )
+             SELECT key FROM doomed",
+        )
+        .bind(self.id)
+        .bind(keys)
+        .fetch_all(pool)
+        .await?;
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_tokens_are_prefixed_and_random() {
+        let a = new_api_token();
+        let b = new_api_token();
+        assert!(a.starts_with(API_TOKEN_PREFIX));
+        assert_eq!(a.len(), API_TOKEN_PREFIX.len() + API_TOKEN_CHARS);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn name_validation() {
+        assert!(is_valid_name("ci-bot"));
+        assert!(!is_valid_name("  "));
+        assert!(!is_valid_name("a\nb"));
+        assert!(!is_valid_name(&"x".repeat(MAX_NAME_LEN + 1)));
+    }
+}
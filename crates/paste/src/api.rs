@@ -0,0 +1,113 @@
+//! Versioned JSON API, served under `/api/v1`.
+//!
+//! The routes mirror the unversioned ones — `POST /new`, `/json/{key}`,
+//! `/raw/{key}`, `/meta/{key}` and the revision routes — but every failure
+//! is a [`PasteError`] with a stable `code`, so clients can tell a missing
+//! key from a wrong one, an expired paste from one that never existed, or a
+//! storage outage from either.
+
+use axum::{
+    body::Bytes,
+    extract::{rejection::BytesRejection, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::error::PasteError;
+use crate::handlers::{self, NewPasteQueryParams, PasteContent, RawParams};
+use crate::models;
+use crate::ratelimit;
+use crate::State as AppState;
+
+fn encryption_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("x-paste-encryption-key")
+        .and_then(|h| h.to_str().ok())
+}
+
+/// `POST /api/v1/pastes` — create a paste from the raw body, with the query
+/// parameters and headers of `POST /new`.
+pub async fn create_paste(
+    State(state): State<AppState>,
+    Query(params): Query<NewPasteQueryParams>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Response, PasteError> {
+    let body = body.map_err(|e| match e.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => PasteError::TooLarge,
+        _ => PasteError::Invalid(e.body_text()),
+    })?;
+    handlers::new_paste_raw(&state, &params, &headers, body).await
+}
+
+/// `GET /api/v1/pastes/{key}` — the paste as JSON; counts as a view.
+pub async fn get_paste(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, PasteError> {
+    let paste = models::Paste::touch_and_get(
+        &state.db,
+        state.store.as_ref(),
+        &state.config,
+        &key,
+        encryption_key(&headers),
+        ratelimit::client_ip(&headers),
+    )
+    .await?;
+    Ok(Json(json!({ "paste": PasteContent::from(paste) })))
+}
+
+/// `GET /api/v1/pastes/{key}/raw` — the content as-is; counts as a view.
+pub async fn get_raw(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(params): Query<RawParams>,
+    headers: HeaderMap,
+) -> Result<Response, PasteError> {
+    handlers::raw_response(&state, &key, None, params.download(), &headers).await
+}
+
+/// `GET /api/v1/pastes/{key}/meta` — see [`models::Paste::meta`].
+pub async fn get_meta(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<impl IntoResponse, PasteError> {
+    let meta = models::Paste::meta(&state.db, &key)
+        .await?
+        .ok_or(PasteError::NotFound)?;
+    Ok(Json(json!({ "paste": meta })))
+}
+
+/// `GET /api/v1/pastes/{key}/revisions` — see [`models::Paste::history`].
+pub async fn list_revisions(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<impl IntoResponse, PasteError> {
+    let revisions = models::Paste::history(&state.db, &key)
+        .await?
+        .ok_or(PasteError::NotFound)?;
+    Ok(Json(json!({ "key": key, "revisions": revisions })))
+}
+
+/// `GET /api/v1/pastes/{key}/revisions/{rev}` — see
+/// [`models::Paste::get_revision`].
+pub async fn get_revision(
+    State(state): State<AppState>,
+    Path((key, rev)): Path<(String, i32)>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, PasteError> {
+    let paste = models::Paste::get_revision(
+        &state.db,
+        state.store.as_ref(),
+        &state.config,
+        &key,
+        rev,
+        encryption_key(&headers),
+        ratelimit::client_ip(&headers),
+    )
+    .await?;
+    Ok(Json(json!({ "paste": PasteContent::from(paste) })))
+}
@@ -5,24 +5,130 @@ use std::io::Read;
 pub struct Config {
     pub version: String,
 
-    // key used for encrypting pastes when no user key is provided;
+    // primary key, used for encrypting pastes when no user key is provided;
     // parsed from "[id:]material" — see [`common::crypto::Key`]
     pub encryption_key: common::crypto::Key,
+    // retired keys: still accepted for decryption, while the re-encryption
+    // job moves their blobs onto the primary key
+    pub retired_encryption_keys: Vec<common::crypto::Key>,
     // key used to derive signature of paste content
     pub signing_key: String,
+    // address of the internal listener serving the operator endpoints
+    // (/metrics, /status/keys, /reconcile), which are kept off the public
+    // router; empty disables it
+    pub admin_addr: String,
+    // how stale the database-backed gauges on /metrics may get before a
+    // scrape recomputes them
+    pub metrics_refresh_seconds: u64,
+
+    // compression applied to new blobs: "zstd" (default), "deflate", or "none"
+    pub blob_compression: crate::storage::Compression,
+    // key derivation for new user-key pastes, recorded in their blobs so it
+    // can change without breaking old ones — see [`common::crypto::Kdf`]
+    pub kdf: common::crypto::Kdf,
 
     pub max_paste_bytes: usize,
     pub max_paste_age_seconds: i64,
 
+    // per-client limits on paste creation and edits, shared across instances
+    // through the database; 0 disables a limit
+    pub rate_limit_requests_per_minute: u64,
+    pub rate_limit_bytes_per_hour: u64,
+
+    // wrong user keys tolerated, see crate::keyguard: per client per hour,
+    // and per paste before it's locked for key_lockout_seconds; 0 disables
+    pub key_failures_per_hour: u64,
+    pub key_lockout_failures: i32,
+    pub key_lockout_seconds: u64,
+
+    // background tasks working through the deletion queue on this instance,
+    // each holding a database connection while it runs a job, and how many
+    // failed attempts a deletion gets before it's given up on
+    pub deletion_workers: usize,
+    pub deletion_max_attempts: i32,
+
+    // how often the re-encryption job moves blobs off retired keys; 0
+    // disables it
+    pub reencrypt_interval_seconds: u64,
+
+    // storage reconciliation: how often it runs (0 disables), how old an
+    // object or row must be before it's acted on, and whether to only
+    // report what it would do
+    pub reconcile_interval_seconds: u64,
+    pub reconcile_grace_seconds: u64,
+    pub reconcile_dry_run: bool,
+
     pub database_url: String,
 
+    // where the paste service is reachable, used for links in API
+    // responses; no trailing slash
+    pub public_url: String,
+
+    // where paste blobs are stored: "s3" (default), "fs", or "memory"
+    pub storage_backend: StorageBackend,
+    // root directory for the "fs" storage backend
+    pub storage_dir: String,
+
     // S3 / Tigris storage
     pub s3_bucket: String,
     pub s3_endpoint: String,
     pub s3_region: String,
 }
 
+/// Which [`crate::storage::BlobStore`] implementation holds paste blobs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    S3,
+    Fs,
+    Memory,
+}
+
+impl StorageBackend {
+    /// Parse a backend name, falling back to [`StorageBackend::S3`] for
+    /// anything unrecognised.
+    pub fn parse(s: &str) -> Self {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "fs" | "file" | "local" => Self::Fs,
+            "memory" | "mem" => Self::Memory,
+            _ => Self::S3,
+        }
+    }
+}
+
+/// Parse a comma-separated key ring, primary key first.  Retired keys whose
+/// id repeats an earlier key's are dropped, since the id is how a blob picks
+/// its key.  `None` if `s` holds no keys.
+fn parse_key_ring(s: &str) -> Option<(common::crypto::Key, Vec<common::crypto::Key>)> {
+    let mut keys = s
+        .split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(common::crypto::Key::parse);
+    let primary = keys.next()?;
+    let mut retired: Vec<common::crypto::Key> = Vec::new();
+    for key in keys {
+        if key.id == primary.id || retired.iter().any(|k| k.id == key.id) {
+            tracing::warn!(
+                "Ignoring paste encryption key with duplicate id {:?}",
+                key.id
+            );
+            continue;
+        }
+        retired.push(key);
+    }
+    Some((primary, retired))
+}
+
 impl Config {
+    /// Every configured server key, primary first, for decryption.
+    pub fn key_ring(&self) -> Vec<common::crypto::KeyRef<'_>> {
+        std::iter::once(&self.encryption_key)
+            .chain(&self.retired_encryption_keys)
+            .map(common::crypto::Key::as_key_ref)
+            .collect()
+    }
+
     pub fn load() -> Self {
         let version = std::fs::File::open("commit_hash.txt")
             .map(|mut f| {
@@ -32,16 +138,37 @@ impl Config {
             })
             .unwrap_or_else(|_| "unknown".to_string());
 
+        // PASTE_ENCRYPTION_KEYS ("primary,retired,...") takes precedence over
+        // the single PASTE_ENCRYPTION_KEY.
+        let (encryption_key, retired_encryption_keys) = parse_key_ring(&common::utils::env_or(
+            "PASTE_ENCRYPTION_KEYS",
+            "",
+        ))
+        .unwrap_or_else(|| {
+            let key =
+                common::utils::env_or("PASTE_ENCRYPTION_KEY", "01234567890123456789012345678901");
+            (common::crypto::Key::parse(&key), Vec::new())
+        });
+
         Self {
             version,
-            encryption_key: common::crypto::Key::parse(&common::utils::env_or(
-                "PASTE_ENCRYPTION_KEY",
-                "01234567890123456789012345678901",
-            )),
+            encryption_key,
+            retired_encryption_keys,
             signing_key: common::utils::env_or(
                 "PASTE_SIGNING_KEY",
                 "01234567890123456789012345678901",
             ),
+            admin_addr: common::utils::env_or("PASTE_ADMIN_ADDR", "127.0.0.1:9091"),
+            metrics_refresh_seconds: common::utils::env_or("PASTE_METRICS_REFRESH_SECONDS", "30")
+                .parse()
+                .unwrap_or(30),
+            blob_compression: crate::storage::Compression::parse(&common::utils::env_or(
+                "PASTE_BLOB_COMPRESSION",
+                "zstd",
+            )),
+            kdf: common::utils::env_or("PASTE_KDF", "argon2id")
+                .parse()
+                .unwrap_or_default(),
             max_paste_bytes: common::utils::env_or("MAX_PASTE_BYTES", "1000000")
                 .parse()
                 .unwrap_or(1_000_000),
@@ -49,10 +176,95 @@ impl Config {
             max_paste_age_seconds: common::utils::env_or("MAX_PASTE_AGE_SECONDS", "2592000")
                 .parse()
                 .unwrap_or(2_592_000),
+            rate_limit_requests_per_minute: common::utils::env_or(
+                "PASTE_RATE_LIMIT_REQUESTS_PER_MINUTE",
+                "30",
+            )
+            .parse()
+            .unwrap_or(30),
+            rate_limit_bytes_per_hour: common::utils::env_or(
+                "PASTE_RATE_LIMIT_BYTES_PER_HOUR",
+                "50000000",
+            )
+            .parse()
+            .unwrap_or(50_000_000),
+            key_failures_per_hour: common::utils::env_or("PASTE_KEY_FAILURES_PER_HOUR", "60")
+                .parse()
+                .unwrap_or(60),
+            key_lockout_failures: common::utils::env_or("PASTE_KEY_LOCKOUT_FAILURES", "10")
+                .parse()
+                .unwrap_or(10),
+            // 60 * 60
+            key_lockout_seconds: common::utils::env_or("PASTE_KEY_LOCKOUT_SECONDS", "3600")
+                .parse()
+                .unwrap_or(3600),
+            deletion_workers: common::utils::env_or("PASTE_DELETION_WORKERS", "2")
+                .parse()
+                .unwrap_or(2),
+            deletion_max_attempts: common::utils::env_or("PASTE_DELETION_MAX_ATTEMPTS", "10")
+                .parse()
+                .unwrap_or(10),
+            reencrypt_interval_seconds: common::utils::env_or(
+                "PASTE_REENCRYPT_INTERVAL_SECONDS",
+                "60",
+            )
+            .parse()
+            .unwrap_or(60),
+            // 60 * 60 * 24
+            reconcile_interval_seconds: common::utils::env_or(
+                "PASTE_RECONCILE_INTERVAL_SECONDS",
+                "86400",
+            )
+            .parse()
+            .unwrap_or(86_400),
+            reconcile_grace_seconds: common::utils::env_or(
+                "PASTE_RECONCILE_GRACE_SECONDS",
+                "86400",
+            )
+            .parse()
+            .unwrap_or(86_400),
+            reconcile_dry_run: common::utils::env_or("PASTE_RECONCILE_DRY_RUN", "false")
+                .parse()
+                .unwrap_or(false),
             database_url: common::utils::env_or("PASTE_DATABASE_URL", "postgres://localhost/paste"),
+            public_url: common::utils::env_or("PASTE_PUBLIC_URL", "http://localhost:3003/paste")
+                .trim_end_matches('/')
+                .to_string(),
+            storage_backend: StorageBackend::parse(&common::utils::env_or(
+                "PASTE_STORAGE_BACKEND",
+                "s3",
+            )),
+            storage_dir: common::utils::env_or("PASTE_STORAGE_DIR", "local/paste-blobs"),
             s3_bucket: common::utils::env_or("PASTE_S3_BUCKET", "kom-paste"),
             s3_endpoint: common::utils::env_or("AWS_ENDPOINT_URL_S3", "https://t3.storage.dev"),
             s3_region: common::utils::env_or("AWS_REGION", "auto"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_ring_primary_first() {
+        let (primary, retired) = parse_key_ring("v3:new, v2:older ,v1:oldest").unwrap();
+        assert_eq!(primary.id, "v3");
+        let ids: Vec<&str> = retired.iter().map(|k| k.id.as_str()).collect();
+        assert_eq!(ids, vec!["v2", "v1"]);
+    }
+
+    #[test]
+    fn key_ring_drops_duplicate_ids() {
+        let (primary, retired) = parse_key_ring("v2:a,v2:b,other,more").unwrap();
+        assert_eq!(primary.id, "v2");
+        let ids: Vec<&str> = retired.iter().map(|k| k.id.as_str()).collect();
+        assert_eq!(ids, vec!["default"]);
+    }
+
+    #[test]
+    fn key_ring_empty() {
+        assert!(parse_key_ring("").is_none());
+        assert!(parse_key_ring(" , ").is_none());
+    }
+}
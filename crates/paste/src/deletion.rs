@@ -0,0 +1,145 @@
+//! Durable paste deletion queue.
+//!
+//! Pastes to delete are rows in `paste_deletion_jobs`, so queued work
+//! survives restarts and is shared by every instance.  Workers claim due
+//! jobs with `FOR UPDATE SKIP LOCKED` and run
+//! [`Paste::attempt_deletion_on`] in the claiming transaction, so a worker
+//! needs one connection and the paste and its job go together.  A failure
+//! is retried after an exponential
+//! backoff, and after [`crate::Config::deletion_max_attempts`] failures the
+//! job moves to `paste_deletion_dead_letters` for someone to look at.
+
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::models::{DeletionRequest, Paste};
+use crate::storage::BlobStore;
+use crate::Config;
+
+/// Delay before the first retry; doubled for each further failure.
+const BACKOFF_BASE: Duration = Duration::from_secs(30);
+
+/// Longest delay between retries.
+const BACKOFF_MAX: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Delay before retrying a job that has failed `attempts` times.
+pub fn backoff(attempts: i32) -> Duration {
+    let exp = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    BACKOFF_BASE.saturating_mul(1 << exp).min(BACKOFF_MAX)
+}
+
+/// What [`run_next`] did.
+#[derive(Debug, PartialEq, Eq)]
+pub enum JobOutcome {
+    /// No job was due.
+    Idle,
+    Deleted,
+    /// The attempt failed and the job will be retried.
+    Retrying,
+    /// The attempt failed for the last time; the job is now a dead letter.
+    DeadLettered,
+}
+
+/// Claim one due job and run it.
+pub async fn run_next(
+    pool: &common::db::DbPool,
+    store: &dyn BlobStore,
+    config: &Config,
+) -> anyhow::Result<JobOutcome> {
+    let mut tx = pool.begin().await?;
+    let job: Option<(i32, String, DateTime<Utc>, i32)> = sqlx::query_as(
+        "SELECT paste_id, storage_uri, paste_created, attempts FROM paste_deletion_jobs
+         WHERE run_after <= NOW()
+         ORDER BY run_after
+         LIMIT 1
+         FOR UPDATE SKIP LOCKED",
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((id, storage_uri, date_created, attempts)) = job else {
+        return Ok(JobOutcome::Idle);
+    };
+    let req = DeletionRequest {
+        id,
+        storage_uri,
+        date_created,
+    };
+
+    let outcome = match Paste::attempt_deletion_on(&mut tx, store, &req).await {
+        Ok(()) => {
+            sqlx::query("DELETE FROM paste_deletion_jobs WHERE paste_id = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            info!("Deleted paste id={id}");
+            JobOutcome::Deleted
+        }
+        Err(e) if attempts + 1 >= config.deletion_max_attempts => {
+            sqlx::query(
+                "WITH job AS (DELETE FROM paste_deletion_jobs WHERE paste_id = $1 RETURNING *)
+                 INSERT INTO paste_deletion_dead_letters
+                     (paste_id, storage_uri, paste_created, attempts, last_error, date_queued)
+                 SELECT paste_id, storage_uri, paste_created, attempts + 1, $2, date_queued FROM job
+                 ON CONFLICT (paste_id) DO UPDATE SET
+                     attempts = excluded.attempts, last_error = excluded.last_error,
+                     date_failed = NOW()",
+            )
+            .bind(id)
+            .bind(e.to_string())
+            .execute(&mut *tx)
+            .await?;
+            warn!(
+                "Giving up deleting paste id={id} after {} attempts: {e}",
+                attempts + 1
+            );
+            JobOutcome::DeadLettered
+        }
+        Err(e) => {
+            let delay = backoff(attempts + 1);
+            sqlx::query(
+                "UPDATE paste_deletion_jobs
+                 SET attempts = attempts + 1, last_error = $2,
+                     run_after = NOW() + make_interval(secs => $3)
+                 WHERE paste_id = $1",
+            )
+            .bind(id)
+            .bind(e.to_string())
+            .bind(delay.as_secs_f64())
+            .execute(&mut *tx)
+            .await?;
+            warn!(
+                "Failed to delete paste id={id}, retrying in {}s: {e}",
+                delay.as_secs()
+            );
+            JobOutcome::Retrying
+        }
+    };
+    tx.commit().await?;
+    Ok(outcome)
+}
+
+/// Jobs waiting (including ones backing off) and dead letters.
+pub async fn queue_depth(pool: &common::db::DbPool) -> anyhow::Result<(i64, i64)> {
+    let depth = sqlx::query_as(
+        "SELECT (SELECT COUNT(*) FROM paste_deletion_jobs),
+                (SELECT COUNT(*) FROM paste_deletion_dead_letters)",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(1), Duration::from_secs(30));
+        assert_eq!(backoff(2), Duration::from_secs(60));
+        assert_eq!(backoff(4), Duration::from_secs(240));
+        assert_eq!(backoff(20), BACKOFF_MAX);
+        assert_eq!(backoff(i32::MAX), BACKOFF_MAX);
+    }
+}
@@ -0,0 +1,528 @@
+//! Content-type detection for pastes uploaded without an explicit `type`.
+//!
+//! Types are ACE mode names from [`CONTENT_TYPES`].  Detection tries, in
+//! order: the file name (multi-file pastes), a shebang line, well-known
+//! headers (`<?xml`, `diff --git`, JSON, ...), then keyword heuristics.
+//! Anything undecided is `"text"`.
+
+use crate::models::CONTENT_TYPES;
+
+/// The `type` that asks for detection; also the default.
+pub const AUTO: &str = "auto";
+
+/// Fallback when nothing matches.
+const TEXT: &str = "text";
+
+/// Only this much of the content is inspected.
+const SCAN_BYTES: usize = 64 * 1024;
+
+/// Common names for types that ACE calls something else.
+const ALIASES: &[(&str, &str)] = &[
+    ("bash", "sh"),
+    ("c", "c_cpp"),
+    ("c++", "c_cpp"),
+    ("cpp", "c_cpp"),
+    ("cs", "csharp"),
+    ("docker", "dockerfile"),
+    ("go", "golang"),
+    ("h", "c_cpp"),
+    ("hpp", "c_cpp"),
+    ("js", "javascript"),
+    ("kt", "kotlin"),
+    ("md", "markdown"),
+    ("plain", "text"),
+    ("plaintext", "text"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("rs", "rust"),
+    ("shell", "sh"),
+    ("ts", "typescript"),
+    ("txt", "text"),
+    ("yml", "yaml"),
+    ("zsh", "sh"),
+];
+
+/// File extensions (lowercase, without the dot) and the type they imply.
+pub(crate) const EXTENSIONS: &[(&str, &str)] = &[
+    ("bat", "batchfile"),
+    ("c", "c_cpp"),
+    ("cc", "c_cpp"),
+    ("clj", "clojure"),
+    ("cpp", "c_cpp"),
+    ("cs", "csharp"),
+    ("css", "css"),
+    ("dart", "dart"),
+    ("diff", "diff"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("erl", "erlang"),
+    ("go", "golang"),
+    ("h", "c_cpp"),
+    ("hpp", "c_cpp"),
+    ("hs", "haskell"),
+    ("htm", "html"),
+    ("html", "html"),
+    ("ini", "ini"),
+    ("java", "java"),
+    ("jl", "julia"),
+    ("js", "javascript"),
+    ("json", "json"),
+    ("jsx", "jsx"),
+    ("kt", "kotlin"),
+    ("kts", "kotlin"),
+    ("less", "less"),
+    ("lua", "lua"),
+    ("m", "objectivec"),
+    ("md", "markdown"),
+    ("ml", "ocaml"),
+    ("nix", "nix"),
+    ("patch", "diff"),
+    ("php", "php"),
+    ("pl", "perl"),
+    ("proto", "protobuf"),
+    ("ps1", "powershell"),
+    ("py", "python"),
+    ("r", "r"),
+    ("rb", "ruby"),
+    ("rs", "rust"),
+    ("rst", "rst"),
+    ("sass", "sass"),
+    ("scala", "scala"),
+    ("scss", "scss"),
+    ("sh", "sh"),
+    ("sql", "sql"),
+    ("svg", "svg"),
+    ("swift", "swift"),
+    ("tex", "latex"),
+    ("toml", "toml"),
+    ("ts", "typescript"),
+    ("tsx", "tsx"),
+    ("txt", "text"),
+    ("xml", "xml"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+];
+
+/// Shebang interpreters (basename, version suffix stripped) and their type.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("ash", "sh"),
+    ("bash", "sh"),
+    ("dash", "sh"),
+    ("deno", "javascript"),
+    ("elixir", "elixir"),
+    ("groovy", "groovy"),
+    ("julia", "julia"),
+    ("ksh", "sh"),
+    ("lua", "lua"),
+    ("node", "javascript"),
+    ("perl", "perl"),
+    ("php", "php"),
+    ("pwsh", "powershell"),
+    ("python", "python"),
+    ("rscript", "r"),
+    ("ruby", "ruby"),
+    ("scala", "scala"),
+    ("sh", "sh"),
+    ("swift", "swift"),
+    ("tclsh", "tcl"),
+    ("zsh", "sh"),
+];
+
+/// Keyword heuristics: `(type, [(pattern, weight)])`.  A pattern starting
+/// with `^` must begin a (left-trimmed) line; others may appear anywhere in
+/// it.  The highest total wins if it reaches [`MIN_SCORE`].
+const KEYWORDS: &[(&str, &[(&str, u32)])] = &[
+    (
+        "rust",
+        &[
+            ("^fn ", 2),
+            ("^pub fn ", 3),
+            ("^impl ", 3),
+            ("^use std::", 3),
+            ("let mut ", 2),
+            ("#[derive(", 3),
+            ("println!(", 2),
+        ],
+    ),
+    (
+        "python",
+        &[
+            ("^def ", 2),
+            ("^import ", 1),
+            ("^from ", 1),
+            ("^elif ", 3),
+            ("self.", 1),
+            ("__init__", 3),
+            ("^if __name__ ==", 3),
+        ],
+    ),
+    (
+        "javascript",
+        &[
+            ("^function ", 2),
+            ("^const ", 1),
+            ("console.log(", 3),
+            ("require(", 2),
+            ("=> {", 2),
+            ("document.", 2),
+        ],
+    ),
+    (
+        "typescript",
+        &[
+            ("^interface ", 2),
+            ("^export interface ", 3),
+            (": string", 2),
+            (": number", 2),
+        ],
+    ),
+    (
+        "golang",
+        &[
+            ("^package ", 3),
+            ("^func ", 2),
+            (" := ", 2),
+            ("^import (", 3),
+            ("fmt.", 2),
+        ],
+    ),
+    (
+        "c_cpp",
+        &[
+            ("^#include ", 3),
+            ("int main(", 2),
+            ("std::", 2),
+            ("printf(", 1),
+            ("^#define ", 2),
+        ],
+    ),
+    (
+        "java",
+        &[
+            ("^public class ", 3),
+            ("^import java.", 3),
+            ("System.out.println", 3),
+            ("public static void main", 3),
+        ],
+    ),
+    (
+        "csharp",
+        &[
+            ("^using System", 3),
+            ("^namespace ", 2),
+            ("Console.WriteLine", 3),
+        ],
+    ),
+    (
+        "ruby",
+        &[
+            ("^require '", 2),
+            ("^puts ", 2),
+            ("^end", 1),
+            (".each do", 3),
+            ("^class ", 1),
+        ],
+    ),
+    ("php", &[("<?php", 5), ("$this->", 3), ("^echo ", 1)]),
+    (
+        "sql",
+        &[
+            ("^SELECT ", 2),
+            ("^INSERT INTO ", 3),
+            ("^CREATE TABLE ", 3),
+            ("^ALTER TABLE ", 3),
+            ("^UPDATE ", 1),
+            ("^select ", 2),
+            ("^create table ", 3),
+        ],
+    ),
+    (
+        "sh",
+        &[
+            ("^echo ", 1),
+            ("^fi", 2),
+            ("^export ", 2),
+            ("; then", 3),
+            ("^done", 2),
+            ("$(", 1),
+        ],
+    ),
+    (
+        "css",
+        &[
+            ("^body {", 3),
+            ("^.", 1),
+            ("color: ", 1),
+            ("margin: ", 1),
+            ("padding: ", 1),
+            ("font-family: ", 2),
+        ],
+    ),
+    (
+        "markdown",
+        &[
+            ("^# ", 2),
+            ("^## ", 2),
+            ("^```", 3),
+            ("^- [", 2),
+            ("](http", 2),
+        ],
+    ),
+    (
+        "toml",
+        &[
+            ("^[package]", 4),
+            ("^[dependencies]", 4),
+            ("^[workspace]", 4),
+        ],
+    ),
+    (
+        "haskell",
+        &[
+            ("^module ", 2),
+            ("^import qualified ", 3),
+            (" :: ", 2),
+            ("^main = ", 3),
+        ],
+    ),
+    ("kotlin", &[("^fun ", 3), ("^val ", 2), ("^data class ", 3)]),
+];
+
+/// Lowest keyword score that counts as a detection.
+const MIN_SCORE: u32 = 4;
+
+/// Resolve a requested `type` to a [`CONTENT_TYPES`] entry.
+///
+/// `None`, `""` and `"auto"` detect the type from `file_name` and `content`;
+/// known types and common aliases (`py`, `js`, `bash`, ...) are normalised,
+/// case-insensitively.  Unknown types are an error.
+pub fn resolve_content_type(
+    requested: Option<&str>,
+    file_name: Option<&str>,
+    content: &[u8],
+) -> Result<&'static str, String> {
+    let requested = requested.map(str::trim).unwrap_or("");
+    if requested.is_empty() || requested.eq_ignore_ascii_case(AUTO) {
+        return Ok(detect(file_name, content));
+    }
+    normalize(requested).ok_or_else(|| format!("unknown paste type {requested:?}"))
+}
+
+/// The [`CONTENT_TYPES`] entry for `name` or one of its aliases.
+fn normalize(name: &str) -> Option<&'static str> {
+    let lower = name.to_ascii_lowercase();
+    let name = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == lower)
+        .map_or(lower.as_str(), |(_, t)| *t);
+    CONTENT_TYPES.iter().copied().find(|t| *t == name)
+}
+
+/// Detect the type of `content`, see the module docs.
+pub fn detect(file_name: Option<&str>, content: &[u8]) -> &'static str {
+    if let Some(t) = file_name.and_then(by_file_name) {
+        return t;
+    }
+    let head = &content[..content.len().min(SCAN_BYTES)];
+    // A cut in the middle of a multi-byte char isn't a reason to give up.
+    let text = match std::str::from_utf8(head) {
+        Ok(s) => s,
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return TEXT,
+    };
+    by_shebang(text)
+        .or_else(|| by_header(text, content.len() <= SCAN_BYTES))
+        .or_else(|| by_keywords(text))
+        .unwrap_or(TEXT)
+}
+
+fn by_file_name(name: &str) -> Option<&'static str> {
+    match name {
+        "Dockerfile" | "Containerfile" => return Some("dockerfile"),
+        "Makefile" | "GNUmakefile" | "makefile" => return Some("makefile"),
+        ".gitignore" => return Some("gitignore"),
+        _ => {}
+    }
+    let (_, ext) = name.rsplit_once('.')?;
+    let ext = ext.to_ascii_lowercase();
+    EXTENSIONS.iter().find(|(e, _)| *e == ext).map(|(_, t)| *t)
+}
+
+fn by_shebang(text: &str) -> Option<&'static str> {
+    let line = text.strip_prefix("#!")?.lines().next()?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+    let program = program
+        .trim_end_matches(|c: char| c.is_ascii_digit() || c == '.')
+        .to_ascii_lowercase();
+    INTERPRETERS
+        .iter()
+        .find(|(p, _)| *p == program)
+        .map(|(_, t)| *t)
+}
+
+/// Formats recognisable from how they start.  JSON is only claimed when
+/// `complete` (the whole content was scanned) and it parses.
+fn by_header(text: &str, complete: bool) -> Option<&'static str> {
+    let start = text.trim_start();
+    let lower: String = start
+        .chars()
+        .take(64)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if lower.starts_with("<?php") {
+        Some("php")
+    } else if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        Some("html")
+    } else if lower.starts_with("<svg") {
+        Some("svg")
+    } else if lower.starts_with("<?xml") {
+        Some(if lower.contains("<svg") { "svg" } else { "xml" })
+    } else if start.starts_with("diff --git ")
+        || (start.starts_with("--- ") && text.contains("\n+++ ") && text.contains("\n@@ "))
+    {
+        Some("diff")
+    } else if start.starts_with("\\documentclass") {
+        Some("latex")
+    } else if start.starts_with("%YAML") || start.starts_with("---\n") {
+        Some("yaml")
+    } else if start.starts_with("FROM ") && text.contains("\nRUN ") {
+        Some("dockerfile")
+    } else if complete
+        && (start.starts_with('{') || start.starts_with('['))
+        && serde_json::from_str::<serde::de::IgnoredAny>(start).is_ok()
+    {
+        Some("json")
+    } else {
+        None
+    }
+}
+
+fn by_keywords(text: &str) -> Option<&'static str> {
+    let mut scores = [0u32; KEYWORDS.len()];
+    for line in text.lines().take(500) {
+        let trimmed = line.trim_start();
+        for (score, (_, patterns)) in scores.iter_mut().zip(KEYWORDS) {
+            for (pattern, weight) in patterns.iter() {
+                let hit = match pattern.strip_prefix('^') {
+                    Some(prefix) => trimmed.starts_with(prefix),
+                    None => line.contains(pattern),
+                };
+                if hit {
+                    *score += weight;
+                }
+            }
+        }
+    }
+    scores
+        .iter()
+        .zip(KEYWORDS)
+        .filter(|(score, _)| **score >= MIN_SCORE)
+        .max_by_key(|(score, _)| **score)
+        .map(|(_, (t, _))| *t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_detectable_type_is_a_content_type() {
+        let targets = ALIASES
+            .iter()
+            .chain(EXTENSIONS)
+            .chain(INTERPRETERS)
+            .map(|(_, t)| *t)
+            .chain(KEYWORDS.iter().map(|(t, _)| *t));
+        for t in targets {
+            assert!(CONTENT_TYPES.contains(&t), "{t} not in CONTENT_TYPES");
+        }
+    }
+
+    #[test]
+    fn explicit_types_are_normalized_or_rejected() {
+        assert_eq!(resolve_content_type(Some("rust"), None, b""), Ok("rust"));
+        assert_eq!(
+            resolve_content_type(Some("Python"), None, b""),
+            Ok("python")
+        );
+        assert_eq!(
+            resolve_content_type(Some("js"), None, b""),
+            Ok("javascript")
+        );
+        assert_eq!(resolve_content_type(Some("c++"), None, b""), Ok("c_cpp"));
+        assert!(resolve_content_type(Some("klingon"), None, b"").is_err());
+    }
+
+    #[test]
+    fn auto_detects() {
+        assert_eq!(resolve_content_type(None, None, b"hello"), Ok("text"));
+        assert_eq!(
+            resolve_content_type(Some("auto"), None, b"#!/bin/bash\necho hi\n"),
+            Ok("sh")
+        );
+    }
+
+    #[test]
+    fn detects_by_file_name() {
+        assert_eq!(detect(Some("main.rs"), b""), "rust");
+        assert_eq!(detect(Some("Dockerfile"), b""), "dockerfile");
+        assert_eq!(detect(Some("notes.TXT"), b"fn main() {}"), "text");
+        assert_eq!(detect(Some("README"), b"# Title\n\n## Usage\n"), "markdown");
+    }
+
+    #[test]
+    fn detects_shebangs() {
+        assert_eq!(
+            detect(None, b"#!/usr/bin/env python3\nprint(1)\n"),
+            "python"
+        );
+        assert_eq!(
+            detect(None, b"#!/usr/bin/env -S node --flag\n"),
+            "javascript"
+        );
+        assert_eq!(detect(None, b"#!/bin/sh\n"), "sh");
+        assert_eq!(detect(None, b"#!/usr/bin/perl -w\n"), "perl");
+    }
+
+    #[test]
+    fn detects_headers() {
+        assert_eq!(detect(None, br#"{"a": [1, 2]}"#), "json");
+        assert_eq!(detect(None, b"{ not json"), "text");
+        assert_eq!(detect(None, b"<?xml version=\"1.0\"?>\n<a/>"), "xml");
+        assert_eq!(detect(None, b"<!DOCTYPE html>\n<html></html>"), "html");
+        assert_eq!(
+            detect(None, b"diff --git a/x b/x\n--- a/x\n+++ b/x\n"),
+            "diff"
+        );
+        assert_eq!(
+            detect(None, b"FROM rust:1\nRUN cargo build\n"),
+            "dockerfile"
+        );
+    }
+
+    #[test]
+    fn detects_keywords() {
+        let rust =
+            b"use std::io;\n\n#[derive(Debug)]\nstruct A;\n\nfn main() {\n    let mut x = 1;\n}\n";
+        assert_eq!(detect(None, rust), "rust");
+        let python =
+            b"import os\n\ndef main():\n    if x:\n        pass\n    elif y:\n        pass\n";
+        assert_eq!(detect(None, python), "python");
+        let go = b"package main\n\nimport (\n\t\"fmt\"\n)\n\nfunc main() {\n\tfmt.Println(1)\n}\n";
+        assert_eq!(detect(None, go), "golang");
+        let sql = b"CREATE TABLE t (id INT);\nINSERT INTO t VALUES (1);\n";
+        assert_eq!(detect(None, sql), "sql");
+        assert_eq!(detect(None, b"just some words\nand more"), "text");
+    }
+
+    #[test]
+    fn binary_is_text() {
+        assert_eq!(detect(None, &[0xff, 0xfe, 0x00, 0x01]), "text");
+    }
+}
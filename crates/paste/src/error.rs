@@ -0,0 +1,276 @@
+//! Why a paste couldn't be read or created.
+//!
+//! [`PasteError`] is what the read path in [`crate::models`] and the
+//! creation helpers in [`crate::handlers`] return.  As a response it is the
+//! error format of the versioned API, see [`crate::api`]: a status and a
+//! stable machine-readable `code`.  The older routes keep their own format,
+//! see [`LegacyError`].
+
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::time::Duration;
+use tracing::error;
+
+#[derive(Debug)]
+pub enum PasteError {
+    /// No such readable paste, or its blob is gone.
+    NotFound,
+    /// The paste expired; it is being deleted.
+    Expired,
+    /// The paste is encrypted with a user key and none was given.
+    KeyRequired,
+    /// The given user key doesn't decrypt the paste.
+    WrongKey,
+    /// Too many wrong keys lately, for this paste or from this client; try
+    /// again after the delay.  See [`crate::keyguard`].
+    TooManyAttempts(Duration),
+    /// The client is over its paste creation limits, see
+    /// [`crate::ratelimit`]; try again after the delay.
+    RateLimited(Duration),
+    /// So many wrong keys were given for this paste that it is locked for a
+    /// while.
+    Locked(Duration),
+    /// The upload is over [`crate::Config::max_paste_bytes`].
+    TooLarge,
+    /// The request itself is wrong; the message says how.
+    Invalid(String),
+    /// The API token isn't valid.
+    Unauthorized,
+    /// The blob store failed.
+    StorageUnavailable(anyhow::Error),
+    /// The blob is unreadable or fails its signature check.
+    Corrupt(anyhow::Error),
+    Internal(anyhow::Error),
+}
+
+impl PasteError {
+    /// Stable identifier of the error, for clients to match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound => "not_found",
+            Self::Expired => "expired",
+            Self::KeyRequired => "key_required",
+            Self::WrongKey => "wrong_key",
+            Self::TooManyAttempts(_) => "too_many_attempts",
+            Self::RateLimited(_) => "rate_limited",
+            Self::Locked(_) => "locked",
+            Self::TooLarge => "too_large",
+            Self::Invalid(_) => "invalid_request",
+            Self::Unauthorized => "unauthorized",
+            Self::StorageUnavailable(_) => "storage_unavailable",
+            Self::Corrupt(_) => "corrupt",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Expired => StatusCode::GONE,
+            Self::KeyRequired | Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::WrongKey => StatusCode::FORBIDDEN,
+            Self::TooManyAttempts(_) | Self::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Locked(_) => StatusCode::LOCKED,
+            Self::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::StorageUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Corrupt(_) | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// How long until the request may be retried, for `Retry-After`.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::TooManyAttempts(wait) | Self::RateLimited(wait) | Self::Locked(wait) => {
+                Some(*wait)
+            }
+            _ => None,
+        }
+    }
+
+    /// Log the server-side failures; the others are the client's doing.
+    fn log(&self) {
+        match self {
+            Self::StorageUnavailable(e) => error!("Blob store error: {e:?}"),
+            Self::Corrupt(e) => error!("Corrupt paste blob: {e:?}"),
+            Self::Internal(e) => error!("Internal error: {e:?}"),
+            _ => {}
+        }
+    }
+}
+
+impl std::fmt::Display for PasteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("Paste not found"),
+            Self::Expired => f.write_str("Paste expired"),
+            Self::KeyRequired => f.write_str("x-paste-encryption-key header is required"),
+            Self::WrongKey => f.write_str("Wrong encryption key"),
+            Self::TooManyAttempts(wait) => write!(
+                f,
+                "Too many wrong keys; retry in {} seconds",
+                wait.as_secs().max(1)
+            ),
+            Self::RateLimited(wait) => write!(
+                f,
+                "Too many requests; retry in {} seconds",
+                wait.as_secs().max(1)
+            ),
+            Self::Locked(wait) => write!(
+                f,
+                "Paste locked after too many wrong keys; retry in {} seconds",
+                wait.as_secs().max(1)
+            ),
+            Self::TooLarge => f.write_str("Upload too large"),
+            Self::Invalid(msg) => f.write_str(msg),
+            Self::Unauthorized => f.write_str("invalid API token"),
+            Self::StorageUnavailable(_) => f.write_str("Storage unavailable"),
+            Self::Corrupt(_) => f.write_str("Paste content is corrupt"),
+            Self::Internal(_) => f.write_str("Internal server error"),
+        }
+    }
+}
+
+impl std::error::Error for PasteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::StorageUnavailable(e) | Self::Corrupt(e) | Self::Internal(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for PasteError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+impl From<anyhow::Error> for PasteError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Internal(e)
+    }
+}
+
+/// `{"error": {"code": ..., "message": ...}}` with [`PasteError::status`],
+/// and `Retry-After` when there is a [`PasteError::retry_after`].
+impl IntoResponse for PasteError {
+    fn into_response(self) -> Response {
+        self.log();
+        let body = json!({ "error": { "code": self.code(), "message": self.to_string() } });
+        let mut resp = (self.status(), Json(body)).into_response();
+        if let Some(wait) = self.retry_after() {
+            set_retry_after(&mut resp, wait);
+        }
+        resp
+    }
+}
+
+/// The error format of the unversioned routes: `{"error": message}`, with
+/// every unreadable paste a 404 except for a missing key, which is a 400
+/// with the `decryption_key_required` error, and rate limiting, which is a
+/// 429 with the `rate_limited` error.  Like [`PasteError`], it sends
+/// `Retry-After` when there is a wait.
+#[derive(Debug)]
+pub struct LegacyError {
+    pub status: StatusCode,
+    pub body: serde_json::Value,
+    pub retry_after: Option<Duration>,
+}
+
+impl From<PasteError> for LegacyError {
+    fn from(e: PasteError) -> Self {
+        e.log();
+        let retry_after = e.retry_after();
+        let (status, body) = match e {
+            PasteError::NotFound
+            | PasteError::Expired
+            | PasteError::WrongKey
+            | PasteError::Corrupt(_) => {
+                (StatusCode::NOT_FOUND, json!({ "error": "Paste not found" }))
+            }
+            PasteError::KeyRequired => (
+                StatusCode::BAD_REQUEST,
+                json!({
+                    "error": "decryption_key_required",
+                    "message": e.to_string(),
+                }),
+            ),
+            PasteError::RateLimited(_) => (
+                e.status(),
+                json!({ "error": e.code(), "message": e.to_string() }),
+            ),
+            e => (e.status(), json!({ "error": e.to_string() })),
+        };
+        Self {
+            status,
+            body,
+            retry_after,
+        }
+    }
+}
+
+impl IntoResponse for LegacyError {
+    fn into_response(self) -> Response {
+        let mut resp = (self.status, Json(self.body)).into_response();
+        if let Some(wait) = self.retry_after {
+            set_retry_after(&mut resp, wait);
+        }
+        resp
+    }
+}
+
+fn set_retry_after(resp: &mut Response, wait: Duration) {
+    resp.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from(wait.as_secs().max(1)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_format_hides_why_a_paste_is_unreadable() {
+        for e in [
+            PasteError::NotFound,
+            PasteError::Expired,
+            PasteError::WrongKey,
+        ] {
+            let legacy = LegacyError::from(e);
+            assert_eq!(legacy.status, StatusCode::NOT_FOUND);
+            assert_eq!(legacy.body["error"], "Paste not found");
+        }
+        let legacy = LegacyError::from(PasteError::KeyRequired);
+        assert_eq!(legacy.status, StatusCode::BAD_REQUEST);
+        assert_eq!(legacy.body["error"], "decryption_key_required");
+        let legacy = LegacyError::from(PasteError::RateLimited(Duration::from_secs(5)));
+        assert_eq!(legacy.status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(legacy.body["error"], "rate_limited");
+        let legacy = LegacyError::from(PasteError::TooLarge);
+        assert_eq!(legacy.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(legacy.body["error"], "Upload too large");
+    }
+
+    #[test]
+    fn legacy_format_keeps_retry_after() {
+        let wait = Duration::from_secs(90);
+        for e in [
+            PasteError::TooManyAttempts(wait),
+            PasteError::RateLimited(wait),
+            PasteError::Locked(wait),
+        ] {
+            let status = e.status();
+            let resp = LegacyError::from(e).into_response();
+            assert_eq!(resp.status(), status);
+            assert_eq!(resp.headers()[header::RETRY_AFTER], "90");
+        }
+        let resp = LegacyError::from(PasteError::NotFound).into_response();
+        assert!(!resp.headers().contains_key(header::RETRY_AFTER));
+    }
+}
@@ -1,130 +1,1000 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::{HeaderMap, StatusCode},
-    response::{Html, IntoResponse},
-    Json,
+    body::Bytes,
+    extract::{FromRequest, Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
 };
+use base64::Engine as _;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tera::Context;
 use tracing::{error, info};
 
+use crate::accounts;
+use crate::error::{LegacyError, PasteError};
 use crate::models::{self, CONTENT_TYPES};
+use crate::upload::{NewPasteBody, NewPasteForm};
 use crate::State as AppState;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct NewPasteQueryParams {
+    /// Syntax type, see [`crate::detect::resolve_content_type`]; detected
+    /// when missing or `"auto"`.
     #[serde(rename = "type")]
     pub type_: Option<String>,
     pub ttl_seconds: Option<u32>,
+    /// Delete the paste after its first successful read.
+    pub burn_after_reading: Option<bool>,
+    /// Delete the paste after this many successful reads.
+    pub max_views: Option<u32>,
+    /// Delete the paste after this many wrong encryption keys; needs an
+    /// encryption key.  See [`crate::keyguard`].
+    pub burn_after_key_failures: Option<u32>,
+}
+
+impl NewPasteQueryParams {
+    /// These parameters, with any missing taken from `fallback`.
+    fn or(self, fallback: Self) -> Self {
+        Self {
+            type_: self.type_.or(fallback.type_),
+            ttl_seconds: self.ttl_seconds.or(fallback.ttl_seconds),
+            burn_after_reading: self.burn_after_reading.or(fallback.burn_after_reading),
+            max_views: self.max_views.or(fallback.max_views),
+            burn_after_key_failures: self
+                .burn_after_key_failures
+                .or(fallback.burn_after_key_failures),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ViewParams {
     pub encryption_key: Option<String>,
+    /// Set by the read-confirmation page.  Without it the HTML view of a
+    /// burn-after-reading or `max_views` paste only shows that page, so
+    /// link-preview bots can't use up its reads.
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+/// Optional [`ViewParams`] body, accepted either as JSON (from `edit.js`) or
+/// as a plain HTML form post (from the burn confirmation page).
+pub struct ViewBody(pub Option<ViewParams>);
+
+impl<S: Send + Sync> FromRequest<S> for ViewBody {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        if content_type.starts_with("application/json") {
+            let Json(params) = Json::<ViewParams>::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Ok(Self(Some(params)))
+        } else if content_type.starts_with("application/x-www-form-urlencoded") {
+            let Form(params) = Form::<ViewParams>::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Ok(Self(Some(params)))
+        } else {
+            Ok(Self(None))
+        }
+    }
+}
+
+/// One file in a [`NewFilesBody`].
+#[derive(Debug, Deserialize)]
+pub struct NewFile {
+    pub name: String,
+    pub content: String,
+    /// `"utf-8"` (the default) or `"base64"`, as in [`PasteContent`].
+    pub encoding: Option<String>,
+    /// Syntax type; detected when missing or `"auto"`.
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    /// MIME type; inferred from the content when missing.
+    pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewFilesBody {
+    pub files: Vec<NewFile>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewAccountBody {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewTokenParams {
+    /// Label for telling tokens apart, e.g. the CI job using it.
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MinePageParams {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteMineBody {
+    /// Keys to delete.
+    pub keys: Option<Vec<String>>,
+    /// Delete every paste of the account instead; must be set explicitly.
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EditQueryParams {
+    /// Syntax type of the new revision; defaults to the previous one's.
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FileParams {
+    /// Which file of a multi-file paste to show; defaults to the first.
+    pub file: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HighlightParams {
+    /// Which file of a multi-file paste to show; defaults to the first.
+    pub file: Option<String>,
+    /// Lines to mark, `"10"` or `"10-20"`.
+    pub lines: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawParams {
+    /// `?download=1` serves the content as an attachment.
+    #[serde(default)]
+    pub download: Option<String>,
+}
+
+impl RawParams {
+    pub(crate) fn download(&self) -> bool {
+        self.download
+            .as_deref()
+            .is_some_and(|d| !matches!(d, "" | "0" | "false"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteParams {
+    /// Deletion token returned by `POST /new`.
+    pub token: Option<String>,
 }
 
 #[derive(Serialize)]
-struct PasteContent {
+pub(crate) struct PasteContent {
     pub key: String,
+    /// UTF-8 content as-is, or standard base64 when `encoding` is `"base64"`.
     pub content: String,
+    pub encoding: &'static str,
     pub content_type: String,
+    pub mime_type: String,
+    pub rev: i32,
+    pub current_rev: i32,
+    /// Files of a multi-file paste; `content` is then the first file's.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileContent>,
+    /// Decryption parameters of a client-encrypted paste; `content` is then
+    /// the base64 ciphertext.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_encryption: Option<models::ClientParams>,
 }
 
+#[derive(Serialize)]
+pub(crate) struct FileContent {
+    pub name: String,
+    /// Encoded as [`PasteContent::content`].
+    pub content: String,
+    pub encoding: &'static str,
+    pub content_type: String,
+    pub mime_type: String,
+}
+
+/// `bytes` as UTF-8, or as standard base64 when they aren't valid UTF-8.
+fn encode_content(bytes: Vec<u8>) -> (String, &'static str) {
+    match String::from_utf8(bytes) {
+        Ok(s) => (s, "utf-8"),
+        Err(e) => (
+            base64::engine::general_purpose::STANDARD.encode(e.as_bytes()),
+            "base64",
+        ),
+    }
+}
+
+impl From<models::Paste> for PasteContent {
+    fn from(paste: models::Paste) -> Self {
+        // Ciphertext is always base64, even if it happens to be valid UTF-8.
+        let (content, encoding) = match paste.client_params {
+            Some(_) => (
+                base64::engine::general_purpose::STANDARD.encode(&paste.content),
+                "base64",
+            ),
+            None => encode_content(paste.content),
+        };
+        let files = paste
+            .files
+            .into_iter()
+            .map(|f| {
+                let (content, encoding) = encode_content(f.content);
+                FileContent {
+                    name: f.name,
+                    content,
+                    encoding,
+                    content_type: f.content_type,
+                    mime_type: f.mime_type,
+                }
+            })
+            .collect();
+        Self {
+            key: paste.key,
+            content,
+            encoding,
+            content_type: paste.content_type,
+            mime_type: paste.mime_type,
+            rev: paste.rev,
+            current_rev: paste.current_rev,
+            files,
+            client_encryption: paste.client_params,
+        }
+    }
+}
+
+/// Parameters from the `x-paste-client-encryption` header, a JSON object of
+/// strings, which marks the body as already encrypted by the client.  It
+/// can't be combined with server-side encryption (`x-paste-encryption-key`).
+fn client_params(headers: &HeaderMap) -> Result<Option<models::ClientParams>, PasteError> {
+    let Some(value) = headers.get("x-paste-client-encryption") else {
+        return Ok(None);
+    };
+    if headers.contains_key("x-paste-encryption-key") {
+        return Err(PasteError::Invalid(
+            "x-paste-client-encryption can't be combined with x-paste-encryption-key".to_string(),
+        ));
+    }
+    let params = value
+        .to_str()
+        .ok()
+        .and_then(|v| serde_json::from_str::<models::ClientParams>(v).ok())
+        .filter(models::is_valid_client_params)
+        .ok_or_else(|| {
+            PasteError::Invalid("invalid x-paste-client-encryption header".to_string())
+        })?;
+    Ok(Some(params))
+}
+
+/// `POST /new` — create a paste from the raw body, or from a form; see
+/// [`crate::upload`].
 pub async fn new_paste(
     State(state): State<AppState>,
     Query(params): Query<NewPasteQueryParams>,
     headers: HeaderMap,
-    body: String,
-) -> std::result::Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let paste_type = params.type_.unwrap_or_else(|| "auto".to_string());
-    let paste_ttl_seconds = params.ttl_seconds;
+    body: NewPasteBody,
+) -> std::result::Result<Response, LegacyError> {
+    match body {
+        NewPasteBody::Raw(body) => Ok(new_paste_raw(&state, &params, &headers, body).await?),
+        NewPasteBody::Form(form) => new_paste_form(&state, params, &headers, form).await,
+    }
+}
+
+/// A paste uploaded as the raw body, by `POST /new` or the versioned API.
+pub(crate) async fn new_paste_raw(
+    state: &AppState,
+    params: &NewPasteQueryParams,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<Response, PasteError> {
     let encryption_key = headers
         .get("x-paste-encryption-key")
         .and_then(|h| h.to_str().ok());
 
     if body.len() > state.config.max_paste_bytes {
-        return Err((
-            StatusCode::PAYLOAD_TOO_LARGE,
-            Json(json!({ "error": "Upload too large" })),
+        return Err(PasteError::TooLarge);
+    }
+
+    let owner = bearer_user(state, headers).await?;
+    let client_params = client_params(headers)?;
+    let declared_mime = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok());
+    // There's nothing to detect in ciphertext.
+    let detect_from: &[u8] = if client_params.is_some() { b"" } else { &body };
+    let paste_type =
+        crate::detect::resolve_content_type(params.type_.as_deref(), None, detect_from)
+            .map_err(PasteError::Invalid)?;
+    // Ciphertext is opaque bytes whatever the client declares.
+    let mime_type = match client_params {
+        Some(_) => models::BINARY_MIME_TYPE.to_string(),
+        None => models::resolve_mime_type(declared_mime, &body),
+    };
+    insert_paste(
+        state,
+        params,
+        encryption_key,
+        models::NewPaste {
+            content: body.to_vec(),
+            content_type: paste_type.to_string(),
+            mime_type,
+            client_params,
+            owner_id: owner.map(|u| u.id),
+            ..Default::default()
+        },
+    )
+    .await
+    .map(|Json(created)| created_response(headers, created))
+}
+
+/// A paste uploaded as a form.  Browsers posting a plain HTML form are
+/// redirected to the new paste rather than shown JSON.
+async fn new_paste_form(
+    state: &AppState,
+    params: NewPasteQueryParams,
+    headers: &HeaderMap,
+    form: NewPasteForm,
+) -> std::result::Result<Response, LegacyError> {
+    let bad_request = |msg: &str| LegacyError::from(PasteError::Invalid(msg.to_string()));
+    if form.total_bytes() > state.config.max_paste_bytes {
+        return Err(PasteError::TooLarge.into());
+    }
+    if headers.contains_key("x-paste-client-encryption") {
+        return Err(bad_request(
+            "client-encrypted pastes must be uploaded as a raw body",
         ));
     }
+    let owner = bearer_user(state, headers).await?;
+    let params = form.params.or(params);
+    let encryption_key = form.encryption_key.as_deref().or_else(|| {
+        headers
+            .get("x-paste-encryption-key")
+            .and_then(|h| h.to_str().ok())
+    });
+
+    let new_paste = match (form.content, form.files.is_empty()) {
+        (Some(_), false) => return Err(bad_request("send either content or files, not both")),
+        (None, true) => return Err(bad_request("missing content field or file part")),
+        (Some(content), true) => {
+            let paste_type =
+                crate::detect::resolve_content_type(params.type_.as_deref(), None, &content)
+                    .map_err(|e| bad_request(&e))?;
+            models::NewPaste {
+                mime_type: models::resolve_mime_type(None, &content),
+                content_type: paste_type.to_string(),
+                content,
+                owner_id: owner.map(|u| u.id),
+                ..Default::default()
+            }
+        }
+        (None, false) => {
+            if form.files.len() > models::MAX_PASTE_FILES {
+                return Err(bad_request(&format!(
+                    "a paste holds 1 to {} files",
+                    models::MAX_PASTE_FILES
+                )));
+            }
+            let mut files: Vec<models::PasteFile> = Vec::with_capacity(form.files.len());
+            for file in form.files {
+                if !models::is_valid_file_name(&file.name) {
+                    return Err(bad_request(&format!("invalid file name {:?}", file.name)));
+                }
+                if files.iter().any(|f| f.name == file.name) {
+                    return Err(bad_request(&format!("duplicate file name {:?}", file.name)));
+                }
+                let content_type = crate::detect::resolve_content_type(
+                    params.type_.as_deref(),
+                    Some(&file.name),
+                    &file.content,
+                )
+                .map_err(|e| bad_request(&e))?;
+                files.push(models::PasteFile {
+                    mime_type: models::resolve_mime_type(file.mime_type.as_deref(), &file.content),
+                    content_type: content_type.to_string(),
+                    name: file.name,
+                    content: file.content,
+                });
+            }
+            let first = &files[0];
+            models::NewPaste {
+                content_type: first.content_type.clone(),
+                mime_type: first.mime_type.clone(),
+                files,
+                owner_id: owner.map(|u| u.id),
+                ..Default::default()
+            }
+        }
+    };
+
+    let Json(created) = insert_paste(state, &params, encryption_key, new_paste).await?;
+    let from_browser = headers
+        .get(header::ACCEPT)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    match created["key"].as_str() {
+        Some(key) if from_browser => {
+            Ok(Redirect::to(&format!("/paste/view/{key}")).into_response())
+        }
+        _ => Ok(created_response(headers, created)),
+    }
+}
+
+/// `POST /new/files` — create a multi-file paste from a JSON list of files.
+/// Takes the same query parameters and headers as [`new_paste`].
+pub async fn new_paste_files(
+    State(state): State<AppState>,
+    Query(params): Query<NewPasteQueryParams>,
+    headers: HeaderMap,
+    Json(body): Json<NewFilesBody>,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let encryption_key = headers
+        .get("x-paste-encryption-key")
+        .and_then(|h| h.to_str().ok());
+    let bad_request = |msg: &str| LegacyError::from(PasteError::Invalid(msg.to_string()));
+    let owner = bearer_user(&state, &headers).await?;
+
+    if body.files.is_empty() || body.files.len() > models::MAX_PASTE_FILES {
+        return Err(bad_request(&format!(
+            "a paste holds 1 to {} files",
+            models::MAX_PASTE_FILES
+        )));
+    }
+    let mut files: Vec<models::PasteFile> = Vec::with_capacity(body.files.len());
+    let mut total_bytes = 0;
+    for file in body.files {
+        if !models::is_valid_file_name(&file.name) {
+            return Err(bad_request(&format!("invalid file name {:?}", file.name)));
+        }
+        if files.iter().any(|f| f.name == file.name) {
+            return Err(bad_request(&format!("duplicate file name {:?}", file.name)));
+        }
+        let content = match file.encoding.as_deref() {
+            None | Some("utf-8") => file.content.into_bytes(),
+            Some("base64") => base64::engine::general_purpose::STANDARD
+                .decode(&file.content)
+                .map_err(|_| bad_request(&format!("invalid base64 in {:?}", file.name)))?,
+            Some(other) => return Err(bad_request(&format!("unknown encoding {other:?}"))),
+        };
+        total_bytes += content.len();
+        if total_bytes > state.config.max_paste_bytes {
+            return Err(PasteError::TooLarge.into());
+        }
+        let content_type =
+            crate::detect::resolve_content_type(file.type_.as_deref(), Some(&file.name), &content)
+                .map_err(|e| bad_request(&e))?;
+        files.push(models::PasteFile {
+            mime_type: models::resolve_mime_type(file.mime_type.as_deref(), &content),
+            content_type: content_type.to_string(),
+            name: file.name,
+            content,
+        });
+    }
+
+    let first = &files[0];
+    let new_paste = models::NewPaste {
+        content_type: first.content_type.clone(),
+        mime_type: first.mime_type.clone(),
+        files,
+        owner_id: owner.map(|u| u.id),
+        ..Default::default()
+    };
+    let Json(created) = insert_paste(&state, &params, encryption_key, new_paste).await?;
+    Ok(created_response(&headers, created))
+}
+
+/// Apply the options in `params` to `new_paste`, issue its owner tokens and
+/// insert it.
+async fn insert_paste(
+    state: &AppState,
+    params: &NewPasteQueryParams,
+    encryption_key: Option<&str>,
+    new_paste: models::NewPaste,
+) -> Result<Json<serde_json::Value>, PasteError> {
+    let burn_after_reading = params.burn_after_reading.unwrap_or(false);
+    let max_views = match params.max_views {
+        None => None,
+        Some(n) => match i32::try_from(n) {
+            Ok(n) if n > 0 => Some(n),
+            _ => {
+                return Err(PasteError::Invalid(
+                    "max_views must be a positive integer".to_string(),
+                ))
+            }
+        },
+    };
+    let burn_after_key_failures = match params.burn_after_key_failures {
+        None => None,
+        Some(_) if encryption_key.is_none() => {
+            return Err(PasteError::Invalid(
+                "burn_after_key_failures needs an encryption key".to_string(),
+            ))
+        }
+        Some(n) => match i32::try_from(n) {
+            Ok(n) if n > 0 => Some(n),
+            _ => {
+                return Err(PasteError::Invalid(
+                    "burn_after_key_failures must be a positive integer".to_string(),
+                ))
+            }
+        },
+    };
 
+    let deletion_token = models::new_owner_token();
+    // Read-limited pastes can't be edited: their revisions would be readable
+    // without using up views.
+    let edit_token = (!burn_after_reading && max_views.is_none()).then(models::new_owner_token);
     let new_paste = models::NewPaste {
-        content: body,
-        content_type: paste_type,
+        burn_after_reading,
+        max_views,
+        burn_after_key_failures,
+        deletion_token: deletion_token.clone(),
+        edit_token: edit_token.clone(),
+        ..new_paste
     };
 
     let paste = new_paste
         .insert(
             &state.db,
-            &state.s3,
+            state.store.as_ref(),
             &state.config,
-            paste_ttl_seconds,
+            params.ttl_seconds,
             encryption_key,
         )
         .await
-        .map_err(|e| {
-            error!("Error inserting paste: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": "Internal server error" })),
-            )
-        })?;
+        .map_err(|e| PasteError::Internal(e.context("inserting paste")))?;
 
-    Ok(Json(json!({"message": "success", "key": &paste.key})))
+    Ok(Json(created_json(
+        &state.config,
+        &paste,
+        encryption_key.is_some(),
+        &deletion_token,
+        edit_token.as_deref(),
+    )))
 }
 
-pub async fn view_paste_json(
+/// Response body for a newly created paste, carrying its owner tokens.
+/// `encrypted` is whether it was encrypted with a user key; client-encrypted
+/// pastes count too.
+fn created_json(
+    config: &crate::Config,
+    paste: &models::Paste,
+    encrypted: bool,
+    deletion_token: &str,
+    edit_token: Option<&str>,
+) -> serde_json::Value {
+    let base = &config.public_url;
+    let key = &paste.key;
+    let size_bytes = match paste.files.as_slice() {
+        [] => paste.content.len(),
+        files => files.iter().map(|f| f.content.len()).sum(),
+    };
+    let mut resp = json!({
+        "message": "success",
+        "key": key,
+        "url": format!("{base}/{key}"),
+        "raw_url": format!("{base}/raw/{key}"),
+        "json_url": format!("{base}/json/{key}"),
+        "expires_at": paste.exp_date,
+        "encrypted": encrypted || paste.client_params.is_some(),
+        "size_bytes": size_bytes,
+        "content_type": &paste.content_type,
+        "deletion_token": deletion_token,
+    });
+    if let Some(edit_token) = edit_token {
+        resp["edit_token"] = json!(edit_token);
+    }
+    if let Some(parent_key) = &paste.parent_key {
+        resp["parent_key"] = json!(parent_key);
+    }
+    resp
+}
+
+/// `201 Created` for a new paste described by `body`, from
+/// [`created_json`], with its URL as the `Location`.  Clients that prefer
+/// `text/plain` over JSON get just the URL, so a paste piped through curl
+/// prints a link.
+fn created_response(headers: &HeaderMap, body: serde_json::Value) -> Response {
+    let url = body["url"].as_str().unwrap_or_default().to_string();
+    let mut resp = if prefers_plain_text(headers) {
+        (
+            StatusCode::CREATED,
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            format!("{url}\n"),
+        )
+            .into_response()
+    } else {
+        (StatusCode::CREATED, Json(body)).into_response()
+    };
+    if let Ok(location) = header::HeaderValue::from_str(&url) {
+        resp.headers_mut().insert(header::LOCATION, location);
+    }
+    resp
+}
+
+/// Whether `Accept` lists `text/plain` ahead of JSON.  Quality values are
+/// ignored; clients asking for plain text send just that.
+fn prefers_plain_text(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("")
+        .split(',')
+        .map(|range| range.split(';').next().unwrap_or("").trim())
+        .find(|range| {
+            range.eq_ignore_ascii_case("text/plain")
+                || range.eq_ignore_ascii_case("application/json")
+        })
+        .is_some_and(|range| range.eq_ignore_ascii_case("text/plain"))
+}
+
+/// `POST /{key}/fork` — copy a paste into a new one that records it as its
+/// parent.  The source is read (counting as a view) with the
+/// `x-paste-encryption-key` header, and the fork is encrypted with the same
+/// key.
+pub async fn fork_paste(
     State(state): State<AppState>,
     Path(key): Path<String>,
     headers: HeaderMap,
-) -> std::result::Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let enc_key = headers
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let encryption_key = headers
         .get("x-paste-encryption-key")
         .and_then(|h| h.to_str().ok());
-    let paste = models::Paste::touch_and_get(&state.db, &state.s3, &state.config, &key, enc_key)
-        .await
-        .map_err(|e| {
+    let owner = bearer_user(&state, &headers).await?;
+    let source = match models::Paste::touch_and_get(
+        &state.db,
+        state.store.as_ref(),
+        &state.config,
+        &key,
+        encryption_key,
+        crate::ratelimit::client_ip(&headers),
+    )
+    .await
+    {
+        Ok(paste) => paste,
+        Err(e) => {
             info!("Paste not found or error: {:?}, key: {}", e, key);
-            (
-                StatusCode::NOT_FOUND,
-                Json(json!({ "error": "Paste not found" })),
-            )
-        })?;
+            return Err(e.into());
+        }
+    };
+
+    let deletion_token = models::new_owner_token();
+    let edit_token = models::new_owner_token();
+    let fork = models::NewPaste {
+        content: source.content,
+        content_type: source.content_type,
+        mime_type: source.mime_type,
+        burn_after_reading: false,
+        max_views: None,
+        deletion_token: deletion_token.clone(),
+        edit_token: Some(edit_token.clone()),
+        parent_id: Some(source.id),
+        files: source.files,
+        // A client-encrypted fork is the same ciphertext, opened by the same
+        // client-held key.
+        client_params: source.client_params,
+        owner_id: owner.map(|u| u.id),
+        burn_after_key_failures: None,
+    };
+    let paste = fork
+        .insert(
+            &state.db,
+            state.store.as_ref(),
+            &state.config,
+            None,
+            encryption_key,
+        )
+        .await
+        .map_err(|e| PasteError::Internal(e.context(format!("forking paste key={key}"))))?;
+
+    let created = created_json(
+        &state.config,
+        &paste,
+        encryption_key.is_some(),
+        &deletion_token,
+        Some(&edit_token),
+    );
+    Ok(created_response(&headers, created))
+}
+
+/// `PUT /{key}` — publish the body as a new revision, authorised by the
+/// `x-paste-edit-token` header returned when the paste was created.
+pub async fn edit_paste(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(params): Query<EditQueryParams>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let Some(edit_token) = headers
+        .get("x-paste-edit-token")
+        .and_then(|h| h.to_str().ok())
+        .filter(|t| !t.is_empty())
+    else {
+        return Err(PasteError::Invalid("edit token required".to_string()).into());
+    };
+    let encryption_key = headers
+        .get("x-paste-encryption-key")
+        .and_then(|h| h.to_str().ok());
+
+    if body.len() > state.config.max_paste_bytes {
+        return Err(PasteError::TooLarge.into());
+    }
 
-    let content = PasteContent {
-        key: paste.key,
-        content: paste.content,
-        content_type: paste.content_type,
+    let client_params = client_params(&headers)?;
+    let declared_mime = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok());
+    let mime_type = match client_params {
+        Some(_) => models::BINARY_MIME_TYPE.to_string(),
+        None => models::resolve_mime_type(declared_mime, &body),
+    };
+    let content_type = match params.type_.as_deref() {
+        None => None,
+        Some(requested) => {
+            let detect_from: &[u8] = if client_params.is_some() { b"" } else { &body };
+            let resolved = crate::detect::resolve_content_type(Some(requested), None, detect_from)
+                .map_err(PasteError::Invalid)?;
+            Some(resolved.to_string())
+        }
     };
-    Ok(Json(json!({ "paste": content })))
+    let revision = models::NewRevision {
+        mime_type,
+        content: body.to_vec(),
+        content_type,
+        client_params,
+    };
+
+    // A wrong token looks the same as a missing paste.
+    match revision
+        .publish(
+            &state.db,
+            state.store.as_ref(),
+            &state.config,
+            &key,
+            edit_token,
+            encryption_key,
+        )
+        .await
+    {
+        Ok(Some(rev)) => Ok(Json(json!({"message": "success", "key": key, "rev": rev}))),
+        Ok(None) => Err(PasteError::NotFound.into()),
+        Err(e) => Err(PasteError::Internal(
+            e.context(format!("publishing revision of paste key={key}")),
+        )
+        .into()),
+    }
 }
 
-pub async fn view_paste_raw(
+pub async fn view_paste_json(
     State(state): State<AppState>,
     Path(key): Path<String>,
     headers: HeaderMap,
-) -> std::result::Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let enc_key = headers
+        .get("x-paste-encryption-key")
+        .and_then(|h| h.to_str().ok());
+    let paste = models::Paste::touch_and_get(
+        &state.db,
+        state.store.as_ref(),
+        &state.config,
+        &key,
+        enc_key,
+        crate::ratelimit::client_ip(&headers),
+    )
+    .await
+    .map_err(|e| {
+        info!("Paste not found or error: {:?}, key: {}", e, key);
+        LegacyError::from(e)
+    })?;
+
+    Ok(Json(json!({ "paste": PasteContent::from(paste) })))
+}
+
+pub async fn view_revision_json(
+    State(state): State<AppState>,
+    Path((key, rev)): Path<(String, i32)>,
+    headers: HeaderMap,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
     let enc_key = headers
         .get("x-paste-encryption-key")
         .and_then(|h| h.to_str().ok());
-    match models::Paste::touch_and_get(&state.db, &state.s3, &state.config, &key, enc_key).await {
-        Ok(paste) => Ok(paste.content.into_response()),
+    let paste = models::Paste::get_revision(
+        &state.db,
+        state.store.as_ref(),
+        &state.config,
+        &key,
+        rev,
+        enc_key,
+        crate::ratelimit::client_ip(&headers),
+    )
+    .await
+    .map_err(|e| {
+        info!(
+            "Revision not found or error: {:?}, key: {}, rev: {}",
+            e, key, rev
+        );
+        LegacyError::from(e)
+    })?;
+
+    Ok(Json(json!({ "paste": PasteContent::from(paste) })))
+}
+
+pub async fn history_json(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    match models::Paste::history(&state.db, &key).await {
+        Ok(Some(revisions)) => Ok(Json(json!({ "key": key, "revisions": revisions }))),
+        Ok(None) => Err(PasteError::NotFound.into()),
+        Err(e) => Err(PasteError::Internal(
+            e.context(format!("listing revisions of paste key={key}")),
+        )
+        .into()),
+    }
+}
+
+/// `GET /meta/{key}` — metadata for link checkers and unfurlers.  Doesn't
+/// count as a view, see [`models::Paste::meta`].
+pub async fn paste_meta(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    match models::Paste::meta(&state.db, &key).await {
+        Ok(Some(meta)) => Ok(Json(json!({ "paste": meta }))),
+        Ok(None) => Err(PasteError::NotFound.into()),
+        Err(e) => Err(PasteError::Internal(
+            e.context(format!("reading metadata of paste key={key}")),
+        )
+        .into()),
+    }
+}
+
+/// `HEAD /raw/{key}` — whether the paste exists, and the headers a `GET`
+/// would have, without counting as a view.
+pub async fn view_paste_raw_head(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> std::result::Result<impl IntoResponse, StatusCode> {
+    match models::Paste::meta(&state.db, &key).await {
+        Ok(Some(meta)) => {
+            let mime_type = meta.mime_type.as_deref().unwrap_or(models::TEXT_MIME_TYPE);
+            Ok(raw_headers(crate::mime::raw_content_type(
+                &meta.content_type,
+                mime_type,
+            )))
+        }
+        Ok(None) => Err(StatusCode::NOT_FOUND),
         Err(e) => {
-            if e.to_string().contains("decryption failure") {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({
-                        "error": "decryption_key_required",
-                        "message": "x-paste-encryption-key header is required"
-                    })),
-                ));
+            error!("Error reading metadata of paste key={key}: {e:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub async fn view_paste_raw(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(params): Query<RawParams>,
+    headers: HeaderMap,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    Ok(raw_response(&state, &key, None, params.download(), &headers).await?)
+}
+
+/// `GET /raw/{key}/{filename}` — one file of a multi-file paste.
+pub async fn view_paste_file_raw(
+    State(state): State<AppState>,
+    Path((key, filename)): Path<(String, String)>,
+    Query(params): Query<RawParams>,
+    headers: HeaderMap,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    Ok(raw_response(&state, &key, Some(&filename), params.download(), &headers).await?)
+}
+
+/// `Content-Disposition` for a download named `name`: an ASCII fallback
+/// plus the exact name as RFC 5987 `filename*`.
+fn attachment_disposition(name: &str) -> String {
+    let fallback: String = name
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{fallback}\"; filename*=UTF-8''{}",
+        percent_encode(name)
+    )
+}
+
+/// Headers of a raw response, shared by `GET` and `HEAD`: `content_type`,
+/// and the sandbox that keeps HTML or SVG pastes from running script on our
+/// origin.
+fn raw_headers(content_type: String) -> [(header::HeaderName, String); 3] {
+    [
+        (header::CONTENT_TYPE, content_type),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        (
+            header::CONTENT_SECURITY_POLICY,
+            crate::mime::RAW_CSP.to_string(),
+        ),
+    ]
+}
+
+/// Raw content.  The `Content-Type` comes from [`crate::mime`], and the
+/// response is sandboxed so HTML or SVG pastes can't run script on our
+/// origin.
+pub(crate) async fn raw_response(
+    state: &AppState,
+    key: &str,
+    filename: Option<&str>,
+    download: bool,
+    headers: &HeaderMap,
+) -> Result<Response, PasteError> {
+    let enc_key = headers
+        .get("x-paste-encryption-key")
+        .and_then(|h| h.to_str().ok());
+    match models::Paste::touch_and_get(
+        &state.db,
+        state.store.as_ref(),
+        &state.config,
+        key,
+        enc_key,
+        crate::ratelimit::client_ip(headers),
+    )
+    .await
+    {
+        Ok(paste) => {
+            let client_encryption = paste
+                .client_params
+                .as_ref()
+                .and_then(|p| serde_json::to_string(p).ok())
+                .and_then(|p| header::HeaderValue::from_str(&p).ok());
+            let (download_name, content_type, content) = match filename {
+                None => {
+                    let ext = crate::mime::extension(&paste.content_type, &paste.mime_type);
+                    (
+                        format!("{key}.{ext}"),
+                        crate::mime::raw_content_type(&paste.content_type, &paste.mime_type),
+                        paste.content,
+                    )
+                }
+                Some(name) => match paste.files.into_iter().find(|f| f.name == name) {
+                    Some(file) => (
+                        file.name,
+                        crate::mime::raw_content_type(&file.content_type, &file.mime_type),
+                        file.content,
+                    ),
+                    None => return Err(PasteError::NotFound),
+                },
+            };
+            let mut resp = (raw_headers(content_type), content).into_response();
+            if download {
+                if let Ok(value) =
+                    header::HeaderValue::from_str(&attachment_disposition(&download_name))
+                {
+                    resp.headers_mut()
+                        .insert(header::CONTENT_DISPOSITION, value);
+                }
             }
-            Err((
-                StatusCode::NOT_FOUND,
-                Json(json!({ "error": "Paste not found" })),
-            ))
+            if let Some(value) = client_encryption {
+                resp.headers_mut()
+                    .insert("x-paste-client-encryption", value);
+            }
+            Ok(resp)
+        }
+        Err(e) => {
+            info!("Paste not found or error: {:?}, key: {}", e, key);
+            Err(e)
         }
     }
 }
@@ -132,63 +1002,336 @@ pub async fn view_paste_raw(
 pub async fn view_paste(
     State(state): State<AppState>,
     Path(key): Path<String>,
+    Query(file): Query<FileParams>,
     headers: HeaderMap,
-    body: Option<Json<ViewParams>>,
+    ViewBody(body): ViewBody,
 ) -> impl IntoResponse {
     let mut enc_key = headers
         .get("x-paste-encryption-key")
         .and_then(|h| h.to_str().ok())
         .map(String::from);
 
+    let confirmed = body.as_ref().is_some_and(|p| p.confirm);
     if enc_key.is_none() {
-        if let Some(Json(params)) = body {
-            enc_key = params.encryption_key;
+        if let Some(params) = body {
+            enc_key = params.encryption_key.filter(|k| !k.is_empty());
         }
     }
 
     let mut context = Context::new();
+
+    // Read-limited pastes need an explicit confirmation (a POST from the
+    // confirmation page) before the HTML view reads them.
+    if !confirmed {
+        match models::Paste::is_read_limited(&state.db, &key).await {
+            Ok(Some(true)) => {
+                context.insert("paste_key", &key);
+                context.insert("confirm_url", &format!("/paste/{key}"));
+                return render(&state, "core/burn.html", &context);
+            }
+            Ok(_) => {}
+            Err(e) => error!("Error checking read limits for key={key}: {e:?}"),
+        }
+    }
+
     match models::Paste::touch_and_get(
         &state.db,
-        &state.s3,
+        state.store.as_ref(),
         &state.config,
         &key,
         enc_key.as_deref(),
+        crate::ratelimit::client_ip(&headers),
     )
     .await
     {
-        Ok(paste) => {
-            context.insert("paste_key", &paste.key);
-            context.insert("content", &paste.content);
-            context.insert("content_type", &paste.content_type);
-            context.insert("content_types", &&CONTENT_TYPES[..]);
+        Ok(paste) => insert_paste_context(&mut context, &paste, file.file.as_deref()),
+        Err(PasteError::KeyRequired) => insert_encrypted_context(&mut context, &key),
+        // Return home if not found
+        Err(_) => return home(State(state)).await.into_response(),
+    }
+
+    render(&state, "core/edit.html", &context)
+}
+
+/// `GET /view/{key}` — read-only view with server-side syntax highlighting,
+/// line numbers and `#L{n}` anchors; works without JavaScript.  Like
+/// [`view_paste`], read-limited pastes need a confirming POST, which can
+/// also carry the decryption key.
+pub async fn view_highlighted(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(params): Query<HighlightParams>,
+    headers: HeaderMap,
+    ViewBody(body): ViewBody,
+) -> Response {
+    let confirmed = body.as_ref().is_some_and(|p| p.confirm);
+    let enc_key = headers
+        .get("x-paste-encryption-key")
+        .and_then(|h| h.to_str().ok())
+        .map(String::from)
+        .or_else(|| body.and_then(|p| p.encryption_key.filter(|k| !k.is_empty())));
+
+    let view_url = format!("/paste/view/{key}");
+    let mut context = Context::new();
+    context.insert("paste_key", &key);
+    context.insert("view_url", &view_url);
+
+    if !confirmed {
+        match models::Paste::is_read_limited(&state.db, &key).await {
+            Ok(Some(true)) => {
+                context.insert("confirm_url", &view_url);
+                return render(&state, "core/burn.html", &context);
+            }
+            Ok(_) => {}
+            Err(e) => error!("Error checking read limits for key={key}: {e:?}"),
         }
-        Err(e) => {
-            if e.to_string().contains("decryption failure") {
-                context.insert("paste_key", &key);
-                context.insert("content", &"< encrypted >");
-                context.insert("content_type", &"");
-                context.insert("content_types", &&CONTENT_TYPES[..]);
-                context.insert("encrypted", &true);
-            } else {
-                // Return home if not found
-                return home(State(state)).await.into_response();
+    }
+
+    let paste = match models::Paste::touch_and_get(
+        &state.db,
+        state.store.as_ref(),
+        &state.config,
+        &key,
+        enc_key.as_deref(),
+        crate::ratelimit::client_ip(&headers),
+    )
+    .await
+    {
+        Ok(paste) => paste,
+        Err(PasteError::KeyRequired) => {
+            context.insert("needs_key", &true);
+            return render(&state, "core/view.html", &context);
+        }
+        Err(_) => return home(State(state)).await.into_response(),
+    };
+
+    context.insert("burned", &paste.burn_after_reading);
+    let file = params
+        .file
+        .as_deref()
+        .and_then(|name| paste.file(name))
+        .or(paste.files.first());
+    let (content, content_type, mime_type) = match file {
+        Some(f) => {
+            let names: Vec<&str> = paste.files.iter().map(|f| f.name.as_str()).collect();
+            context.insert("files", &names);
+            context.insert("file_name", &f.name);
+            (f.content_str(), &f.content_type, &f.mime_type)
+        }
+        None => (paste.content_str(), &paste.content_type, &paste.mime_type),
+    };
+    context.insert("mime_type", mime_type);
+    match content {
+        _ if paste.client_params.is_some() => context.insert("client_encrypted", &true),
+        None => context.insert("binary", &true),
+        Some(content) => {
+            let content = content.to_string();
+            let content_type = content_type.clone();
+            // Keep the selected file in line-number links.
+            let query = match file {
+                Some(f) => format!("file={}&amp;", percent_encode(&f.name)),
+                None => String::new(),
+            };
+            let selected = params
+                .lines
+                .as_deref()
+                .and_then(crate::highlight::parse_line_range);
+            let highlighted = tokio::task::spawn_blocking(move || {
+                crate::highlight::highlight_html(&content, &content_type, selected.as_ref(), &query)
+            })
+            .await
+            .unwrap_or_default();
+            context.insert("highlighted", &highlighted);
+        }
+    }
+    render(&state, "core/view.html", &context)
+}
+
+/// Percent-encode everything but unreserved URL characters.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
             }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// HTML view of one revision.  Like [`view_paste`], a POST carries the
+/// decryption key.
+pub async fn view_revision(
+    State(state): State<AppState>,
+    Path((key, rev)): Path<(String, i32)>,
+    Query(file): Query<FileParams>,
+    headers: HeaderMap,
+    ViewBody(body): ViewBody,
+) -> impl IntoResponse {
+    let enc_key = headers
+        .get("x-paste-encryption-key")
+        .and_then(|h| h.to_str().ok())
+        .map(String::from)
+        .or_else(|| body.and_then(|p| p.encryption_key.filter(|k| !k.is_empty())));
+
+    let mut context = Context::new();
+    match models::Paste::get_revision(
+        &state.db,
+        state.store.as_ref(),
+        &state.config,
+        &key,
+        rev,
+        enc_key.as_deref(),
+        crate::ratelimit::client_ip(&headers),
+    )
+    .await
+    {
+        Ok(paste) => insert_paste_context(&mut context, &paste, file.file.as_deref()),
+        Err(PasteError::KeyRequired) => {
+            insert_encrypted_context(&mut context, &key);
+            context.insert("rev", &rev);
         }
+        Err(_) => return home(State(state)).await.into_response(),
     }
 
-    match state.tera.render("core/edit.html", &context) {
-        Ok(content) => Html(content).into_response(),
+    render(&state, "core/edit.html", &context)
+}
+
+/// HTML list of a paste's revisions.
+pub async fn history(State(state): State<AppState>, Path(key): Path<String>) -> Response {
+    match models::Paste::history(&state.db, &key).await {
+        Ok(Some(revisions)) => {
+            let mut context = Context::new();
+            context.insert("paste_key", &key);
+            context.insert("revisions", &revisions);
+            render(&state, "core/history.html", &context)
+        }
+        Ok(None) => home(State(state)).await.into_response(),
         Err(e) => {
-            error!("Tera render error: {:?}", e);
+            error!("Error listing revisions of paste key={key}: {e:?}");
             (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into_response()
         }
     }
 }
 
+/// Fill `context` for `core/edit.html`.  For a multi-file paste, `file`
+/// picks the file shown in the editor (the first by default).
+fn insert_paste_context(context: &mut Context, paste: &models::Paste, file: Option<&str>) {
+    context.insert("paste_key", &paste.key);
+    if let Some(params) = &paste.client_params {
+        // Decrypted in the browser with the key from the URL fragment.
+        context.insert("client_encrypted", &true);
+        context.insert(
+            "client_ciphertext",
+            &base64::engine::general_purpose::STANDARD.encode(&paste.content),
+        );
+        context.insert(
+            "client_params",
+            &serde_json::to_string(params).unwrap_or_default(),
+        );
+    }
+    let (content, content_type, mime_type) = match file
+        .and_then(|name| paste.file(name))
+        .or(paste.files.first())
+    {
+        Some(f) => {
+            let names: Vec<&str> = paste.files.iter().map(|f| f.name.as_str()).collect();
+            context.insert("files", &names);
+            context.insert("file_name", &f.name);
+            (f.content_str(), &f.content_type, &f.mime_type)
+        }
+        None => (paste.content_str(), &paste.content_type, &paste.mime_type),
+    };
+    match content {
+        _ if paste.client_params.is_some() => context.insert("content", "< encrypted >"),
+        Some(content) => context.insert("content", content),
+        None => {
+            context.insert("content", &format!("< binary: {} >", mime_type));
+            context.insert("binary", &true);
+        }
+    }
+    let view_url = if paste.rev == paste.current_rev {
+        format!("/paste/{}", paste.key)
+    } else {
+        format!("/paste/{}/rev/{}", paste.key, paste.rev)
+    };
+    context.insert("view_url", &view_url);
+    context.insert("content_type", content_type);
+    context.insert("content_types", &&CONTENT_TYPES[..]);
+    context.insert("burned", &paste.burn_after_reading);
+    context.insert("rev", &paste.rev);
+    context.insert("current_rev", &paste.current_rev);
+    context.insert("parent_key", &paste.parent_key);
+}
+
+fn insert_encrypted_context(context: &mut Context, key: &str) {
+    context.insert("paste_key", key);
+    context.insert("content", &"< encrypted >");
+    context.insert("content_type", &"");
+    context.insert("content_types", &&CONTENT_TYPES[..]);
+    context.insert("encrypted", &true);
+}
+
+/// `DELETE /{key}` — delete a paste using the deletion token returned when
+/// it was created, passed as `x-paste-deletion-token` or `?token=`.
+pub async fn delete_paste(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(params): Query<DeleteParams>,
+    headers: HeaderMap,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let token = headers
+        .get("x-paste-deletion-token")
+        .and_then(|h| h.to_str().ok())
+        .map(String::from)
+        .or(params.token);
+    delete_with_token(&state, &key, token.as_deref()).await?;
+    Ok(Json(json!({ "message": "deleted", "key": key })))
+}
+
+/// `POST /{key}/delete` — form-friendly variant of [`delete_paste`] taking
+/// the token as a `token` form field.  Redirects home on success.
+pub async fn delete_paste_form(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Form(params): Form<DeleteParams>,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    delete_with_token(&state, &key, params.token.as_deref()).await?;
+    Ok(Redirect::to("/paste/"))
+}
+
+async fn delete_with_token(
+    state: &AppState,
+    key: &str,
+    token: Option<&str>,
+) -> std::result::Result<(), LegacyError> {
+    let Some(token) = token.filter(|t| !t.is_empty()) else {
+        return Err(PasteError::Invalid("deletion token required".to_string()).into());
+    };
+    // A wrong token looks the same as a missing paste.
+    match models::Paste::delete_with_token(
+        &state.db,
+        state.store.as_ref(),
+        &state.config,
+        key,
+        token,
+    )
+    .await
+    {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(PasteError::NotFound.into()),
+        Err(e) => Err(PasteError::Internal(e.context(format!("deleting paste key={key}"))).into()),
+    }
+}
+
 pub async fn home(State(state): State<AppState>) -> impl IntoResponse {
     let mut context = Context::new();
     context.insert("content_types", &&CONTENT_TYPES[..]);
-    match state.tera.render("core/edit.html", &context) {
+    render(&state, "core/edit.html", &context)
+}
+
+fn render(state: &AppState, template: &str, context: &Context) -> Response {
+    match state.tera.render(template, context) {
         Ok(content) => Html(content).into_response(),
         Err(e) => {
             error!("Tera render error: {:?}", e);
@@ -197,6 +1340,236 @@ pub async fn home(State(state): State<AppState>) -> impl IntoResponse {
     }
 }
 
+/// The `Authorization: Bearer` token, if the header holds one.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|a| a.to_str().ok())
+        .and_then(|a| a.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// The account whose API token is sent as `Authorization: Bearer`, if any.
+/// A token that's sent but not recognised is an error rather than a reason
+/// to fall back to anonymous.
+async fn bearer_user(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Option<accounts::User>, PasteError> {
+    if !headers.contains_key(header::AUTHORIZATION) {
+        return Ok(None);
+    }
+    let token = bearer_token(headers).ok_or(PasteError::Unauthorized)?;
+    match accounts::User::authenticate(&state.db, &state.config, token).await {
+        Ok(Some(user)) => Ok(Some(user)),
+        Ok(None) => Err(PasteError::Unauthorized),
+        Err(e) => Err(PasteError::Internal(e.context("authenticating API token"))),
+    }
+}
+
+/// Like [`bearer_user`], but the token is required.
+async fn require_user(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> std::result::Result<accounts::User, LegacyError> {
+    bearer_user(state, headers)
+        .await?
+        .ok_or_else(|| PasteError::Unauthorized.into())
+}
+
+fn internal_error(context: &'static str, e: anyhow::Error) -> PasteError {
+    PasteError::Internal(e.context(context))
+}
+
+/// `POST /api/accounts` — create an account and its first API token.  The
+/// token is only ever shown in this response.
+pub async fn create_account(
+    State(state): State<AppState>,
+    Json(body): Json<NewAccountBody>,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    if !accounts::is_valid_name(&body.name) {
+        return Err(PasteError::Invalid("invalid account name".to_string()).into());
+    }
+    let (user, token) = accounts::User::create(&state.db, &state.config, body.name.trim())
+        .await
+        .map_err(|e| internal_error("creating account", e))?;
+    info!("Created paste account id={}", user.id);
+    Ok(Json(json!({ "account": user, "token": token })))
+}
+
+/// `POST /api/tokens` — issue another API token for the caller's account.
+pub async fn create_api_token(
+    State(state): State<AppState>,
+    Query(params): Query<NewTokenParams>,
+    headers: HeaderMap,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let user = require_user(&state, &headers).await?;
+    if params
+        .name
+        .as_deref()
+        .is_some_and(|n| !accounts::is_valid_name(n))
+    {
+        return Err(PasteError::Invalid("invalid token name".to_string()).into());
+    }
+    let token = user
+        .new_token(&state.db, &state.config, params.name.as_deref())
+        .await
+        .map_err(|e| internal_error("creating API token", e))?;
+    Ok(Json(json!({ "token": token })))
+}
+
+/// `DELETE /api/tokens` — revoke the API token making the request.
+pub async fn revoke_api_token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let user = require_user(&state, &headers).await?;
+    let token = bearer_token(&headers).unwrap_or_default();
+    user.revoke_token(&state.db, &state.config, token)
+        .await
+        .map_err(|e| internal_error("revoking API token", e))?;
+    Ok(Json(json!({ "message": "revoked" })))
+}
+
+/// `GET /api/mine` — the caller's pastes, newest first, paged with `limit`
+/// (default 100) and `offset`.
+pub async fn my_pastes(
+    State(state): State<AppState>,
+    Query(params): Query<MinePageParams>,
+    headers: HeaderMap,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let user = require_user(&state, &headers).await?;
+    let limit = params.limit.unwrap_or(100);
+    let offset = params.offset.unwrap_or(0);
+    let (pastes, total) = user
+        .pastes(&state.db, limit, offset)
+        .await
+        .map_err(|e| internal_error("listing account pastes", e))?;
+    Ok(Json(json!({
+        "account": user,
+        "pastes": pastes,
+        "total": total,
+    })))
+}
+
+/// `DELETE /api/mine` — delete several of the caller's pastes at once: a
+/// JSON body of `{"keys": [...]}`, or `{"all": true}` for every one.
+pub async fn delete_my_pastes(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<DeleteMineBody>,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let user = require_user(&state, &headers).await?;
+    let keys = match (body.keys, body.all) {
+        (Some(_), true) | (None, false) => {
+            return Err(
+                PasteError::Invalid("give either \"keys\" or \"all\": true".to_string()).into(),
+            )
+        }
+        (Some(keys), false) => Some(keys),
+        (None, true) => None,
+    };
+    let deleted = user
+        .delete_pastes(&state.db, keys.as_deref())
+        .await
+        .map_err(|e| internal_error("deleting account pastes", e))?;
+    info!(
+        "Deleting {} pastes of account id={} on request",
+        deleted.len(),
+        user.id
+    );
+    Ok(Json(json!({ "message": "deleted", "keys": deleted })))
+}
+
+/// `GET /metrics` — Prometheus metrics, see [`crate::metrics`], on the
+/// internal listener.  Gauges read from the database are recomputed at most
+/// every [`crate::Config::metrics_refresh_seconds`], however often it's
+/// scraped.
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let metrics = &crate::metrics::METRICS;
+    // Held across the refresh, so concurrent scrapes wait for it rather
+    // than each running the queries.
+    let mut refreshed = state.gauges_refreshed.lock().await;
+    let max_age = std::time::Duration::from_secs(state.config.metrics_refresh_seconds);
+    if refreshed.is_none_or(|at| at.elapsed() >= max_age) {
+        match crate::deletion::queue_depth(&state.db).await {
+            Ok((queued, dead)) => {
+                metrics.deletion_queue_depth.set(queued);
+                metrics.deletion_dead_letters.set(dead);
+            }
+            Err(e) => error!("Error reading paste deletion queue depth: {e:?}"),
+        }
+        match models::Paste::storage_usage(&state.db).await {
+            Ok((bytes, unsized_blobs, missing_blobs)) => {
+                metrics.stored_bytes.set(bytes);
+                metrics.stored_blobs_unsized.set(unsized_blobs);
+                metrics.blobs_missing.set(missing_blobs);
+            }
+            Err(e) => error!("Error reading paste storage usage: {e:?}"),
+        }
+        *refreshed = Some(std::time::Instant::now());
+    }
+    drop(refreshed);
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        metrics.render(),
+    )
+}
+
+/// `GET /reconcile` — a dry run of [`crate::reconcile::reconcile`], on the
+/// internal listener: what the next reconciliation would delete and which
+/// revisions are missing their blob, without changing anything.
+pub async fn reconcile_report(
+    State(state): State<AppState>,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let report = crate::reconcile::reconcile(
+        &state.db,
+        state.store.as_ref(),
+        std::time::Duration::from_secs(state.config.reconcile_grace_seconds),
+        true,
+    )
+    .await
+    .map_err(|e| internal_error("running reconciliation dry run", e))?;
+    Ok(Json(report))
+}
+
+/// `GET /status/keys` — re-encryption progress, on the internal listener:
+/// revision blob counts per encryption key.  A
+/// retired key can be removed from `PASTE_ENCRYPTION_KEYS` once its count
+/// (and the "unknown" count) reaches zero.
+pub async fn key_status(
+    State(state): State<AppState>,
+) -> std::result::Result<impl IntoResponse, LegacyError> {
+    let usage = models::Paste::key_usage(&state.db)
+        .await
+        .map_err(|e| internal_error("counting blobs per key", e))?;
+    let mut blobs = serde_json::Map::new();
+    for retired in &state.config.retired_encryption_keys {
+        blobs.insert(retired.id.clone(), json!(0));
+    }
+    for (key_id, count) in usage {
+        let name = match key_id.as_deref() {
+            None => "unknown",
+            Some(models::USER_KEY_ID) => "user-key",
+            Some(id) => id,
+        };
+        blobs.insert(name.to_string(), json!(count));
+    }
+    Ok(Json(json!({
+        "primary": state.config.encryption_key.id,
+        "retired": state
+            .config
+            .retired_encryption_keys
+            .iter()
+            .map(|k| &k.id)
+            .collect::<Vec<_>>(),
+        "blobs": blobs,
+    })))
+}
+
 pub async fn status() -> impl IntoResponse {
     Json(json!({
         "hash": include_str!("../../../commit_hash.txt").trim(),
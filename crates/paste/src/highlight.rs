@@ -0,0 +1,219 @@
+//! Server-side syntax highlighting for the read-only paste view.
+//!
+//! Output is plain HTML with inline styles — no stylesheet or JavaScript
+//! needed — so it also renders in email clients.  Each line carries an
+//! `id="L{n}"` anchor and a line-number link.
+
+use std::fmt::Write as _;
+use std::ops::RangeInclusive;
+use std::sync::LazyLock;
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Content larger than this is shown unhighlighted; highlighting is
+/// regex-heavy and shouldn't let one paste tie up a worker.
+pub const MAX_HIGHLIGHT_BYTES: usize = 256 * 1024;
+
+/// Theme matching the editor's `tomorrow_night_eighties`.
+const THEME: &str = "base16-eighties.dark";
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// ACE mode names (see [`crate::models::CONTENT_TYPES`]) whose syntect
+/// syntax goes by a different name or extension.
+const ACE_ALIASES: &[(&str, &str)] = &[
+    ("batchfile", "bat"),
+    ("c_cpp", "cpp"),
+    ("clojure", "clj"),
+    ("csharp", "cs"),
+    ("dot", "dot"),
+    ("erlang", "erl"),
+    ("golang", "go"),
+    ("haskell", "hs"),
+    ("javascript", "js"),
+    ("jsx", "js"),
+    ("latex", "tex"),
+    ("makefile", "Makefile"),
+    ("markdown", "md"),
+    ("objectivec", "m"),
+    ("ocaml", "ml"),
+    ("perl", "pl"),
+    ("python", "py"),
+    ("ruby", "rb"),
+    ("rust", "rs"),
+    ("sh", "sh"),
+    ("typescript", "js"),
+];
+
+/// The syntax for an ACE `content_type`, falling back to a shebang / first
+/// line match for `"auto"` and unknown types, then to plain text.
+fn find_syntax(content_type: &str, content: &str) -> &'static SyntaxReference {
+    let token = ACE_ALIASES
+        .iter()
+        .find(|(ace, _)| *ace == content_type)
+        .map_or(content_type, |(_, token)| *token);
+    let by_type = match content_type {
+        "auto" | "text" => None,
+        _ => SYNTAXES.find_syntax_by_token(token),
+    };
+    by_type
+        .or_else(|| SYNTAXES.find_syntax_by_first_line(content))
+        .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text())
+}
+
+fn theme() -> &'static Theme {
+    &THEMES.themes[THEME]
+}
+
+fn css_color(c: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+}
+
+/// Parse a `?lines=` selection: `"10"` or `"10-20"` (either order).
+pub fn parse_line_range(s: &str) -> Option<RangeInclusive<usize>> {
+    let s = s.trim().trim_start_matches('L');
+    let (start, end): (usize, usize) = match s.split_once('-') {
+        Some((a, b)) => (
+            a.trim().parse().ok()?,
+            b.trim().trim_start_matches('L').parse().ok()?,
+        ),
+        None => {
+            let n = s.parse().ok()?;
+            (n, n)
+        }
+    };
+    if start == 0 || end == 0 {
+        return None;
+    }
+    Some(start.min(end)..=start.max(end))
+}
+
+/// Render `content` as a highlighted `<pre>` block, with lines in
+/// `selected` marked.  Line-number links are `?{query}lines={n}#L{n}`, so
+/// `query` (e.g. `"file=main.rs&"`, already escaped) keeps other parameters.
+/// Content over [`MAX_HIGHLIGHT_BYTES`] keeps the line anchors but isn't
+/// highlighted.
+pub fn highlight_html(
+    content: &str,
+    content_type: &str,
+    selected: Option<&RangeInclusive<usize>>,
+    query: &str,
+) -> String {
+    let theme = theme();
+    let background = theme
+        .settings
+        .background
+        .map_or_else(|| "#2d2d2d".to_string(), css_color);
+    let foreground = theme
+        .settings
+        .foreground
+        .map_or_else(|| "#d3d0c8".to_string(), css_color);
+    let line_highlight = theme
+        .settings
+        .line_highlight
+        .map_or_else(|| "#393939".to_string(), css_color);
+    let gutter = theme
+        .settings
+        .gutter_foreground
+        .map_or_else(|| "#747369".to_string(), css_color);
+
+    let mut highlighter = (content.len() <= MAX_HIGHLIGHT_BYTES)
+        .then(|| HighlightLines::new(find_syntax(content_type, content), theme));
+
+    let mut out = format!(
+        "<pre class=\"highlighted\" style=\"background-color:{background};color:{foreground};margin:0;padding:0.5em 0;overflow-x:auto;\">"
+    );
+    for (i, line) in LinesWithEndings::from(content).enumerate() {
+        let n = i + 1;
+        let line_html = highlighter
+            .as_mut()
+            .and_then(|h| h.highlight_line(line, &SYNTAXES).ok())
+            .and_then(|regions| {
+                styled_line_to_highlighted_html(&regions, IncludeBackground::No).ok()
+            })
+            .unwrap_or_else(|| escape_html(line));
+        let line_style = if selected.is_some_and(|r| r.contains(&n)) {
+            format!("display:block;background-color:{line_highlight};")
+        } else {
+            "display:block;".to_string()
+        };
+        let _ = write!(
+            out,
+            "<span id=\"L{n}\" class=\"line\" style=\"{line_style}\"><a href=\"?{query}lines={n}#L{n}\" class=\"line-number\" style=\"color:{gutter};display:inline-block;min-width:4ch;padding:0 1ch;text-align:right;text-decoration:none;user-select:none;\">{n}</a>{}</span>",
+            line_html.trim_end_matches('\n')
+        );
+    }
+    out.push_str("</pre>");
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_line_ranges() {
+        assert_eq!(parse_line_range("10"), Some(10..=10));
+        assert_eq!(parse_line_range("10-20"), Some(10..=20));
+        assert_eq!(parse_line_range("L20-L10"), Some(10..=20));
+        assert_eq!(parse_line_range("0-3"), None);
+        assert_eq!(parse_line_range("x"), None);
+    }
+
+    #[test]
+    fn ace_names_map_to_syntaxes() {
+        assert_eq!(find_syntax("rust", "").name, "Rust");
+        assert_eq!(find_syntax("c_cpp", "").name, "C++");
+        assert_eq!(find_syntax("golang", "").name, "Go");
+        assert_eq!(find_syntax("python", "").name, "Python");
+        assert_eq!(
+            find_syntax("auto", "#!/bin/bash\n").name,
+            "Bourne Again Shell (bash)"
+        );
+        assert_eq!(find_syntax("no-such-mode", "x").name, "Plain Text");
+    }
+
+    #[test]
+    fn renders_anchored_escaped_lines() {
+        let html = highlight_html(
+            "fn main() {}\nlet x = \"<b>\";\n",
+            "rust",
+            Some(&(2..=2)),
+            "file=a&amp;",
+        );
+        assert!(html.contains("id=\"L1\""));
+        assert!(html.contains("href=\"?file=a&amp;lines=2#L2\""));
+        assert!(!html.contains("id=\"L3\""));
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("&lt;b&gt;"));
+        assert!(html.contains("style=\"color:"));
+    }
+
+    #[test]
+    fn oversized_content_is_escaped_not_highlighted() {
+        let content = "<x>\n".repeat(MAX_HIGHLIGHT_BYTES / 4 + 1);
+        let html = highlight_html(&content, "rust", None, "");
+        assert!(html.contains("&lt;x&gt;"));
+        assert!(!html.contains("<span style="));
+    }
+}
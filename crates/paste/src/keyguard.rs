@@ -0,0 +1,368 @@
+//! Brute-force protection for pastes encrypted with a user key.
+//!
+//! Every key tried against a user-key blob is counted before it is tried:
+//! [`reserve`] charges it to the paste, in `pastes.key_failures`, and to the
+//! client, in its `key_failures` rate-limit bucket (see [`crate::ratelimit`]),
+//! so concurrent guesses can't all slip in on the same stale count.  It turns
+//! the attempt away if
+//!
+//! - the paste has had more than [`FREE_FAILURES`] wrong keys and the last
+//!   was too recent — the wait doubles with every further failure;
+//! - the paste has had [`crate::Config::key_lockout_failures`] wrong keys,
+//!   which locks it for [`crate::Config::key_lockout_seconds`]; or
+//! - the client has used up [`crate::Config::key_failures_per_hour`].
+//!
+//! The right key resets the paste's count and gives the client its token
+//! back, see [`right_key`]; an attempt that never got a verdict is taken
+//! back by [`release`].  A paste can also be created with
+//! `burn_after_key_failures`, making [`wrong_key`] burn it once that many
+//! wrong keys have been given.
+
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tracing::{error, warn};
+
+use crate::error::PasteError;
+use crate::metrics::METRICS;
+use crate::models::{DeletionRequest, Paste};
+use crate::ratelimit::{self, Limit};
+use crate::storage::BlobStore;
+use crate::Config;
+
+/// Wrong keys a paste takes before attempts are delayed, so typos cost
+/// nothing.
+pub const FREE_FAILURES: i32 = 3;
+
+/// Wait after the first delayed failure; doubled for each further one.
+const DELAY_BASE: Duration = Duration::from_secs(1);
+
+/// Longest wait between attempts short of a lockout.
+const DELAY_MAX: Duration = Duration::from_secs(15 * 60);
+
+/// Rate-limit bucket counting a client's wrong keys.
+const CLIENT_BUCKET: &str = "key_failures";
+
+/// How long after its last wrong key a paste with `failures` of them
+/// refuses further attempts.
+pub fn delay(failures: i32) -> Duration {
+    if failures <= FREE_FAILURES {
+        return Duration::ZERO;
+    }
+    let exp = (failures - FREE_FAILURES - 1).min(16) as u32;
+    DELAY_BASE.saturating_mul(1 << exp).min(DELAY_MAX)
+}
+
+/// Whether a paste with `failures` wrong keys, the last at `last_failure`,
+/// takes another attempt at `now`.  It is locked for `lockout` once it has
+/// `lockout_failures` of them, unless that is 0.
+pub fn check_paste(
+    failures: i32,
+    last_failure: Option<DateTime<Utc>>,
+    lockout_failures: i32,
+    lockout: Duration,
+    now: DateTime<Utc>,
+) -> Result<(), PasteError> {
+    let Some(last_failure) = last_failure else {
+        return Ok(());
+    };
+    let elapsed = (now - last_failure).to_std().unwrap_or_default();
+    let locked = lockout_failures > 0 && failures >= lockout_failures;
+    let (wait, err): (_, fn(Duration) -> PasteError) = if locked {
+        (lockout, PasteError::Locked)
+    } else {
+        (delay(failures), PasteError::TooManyAttempts)
+    };
+    match wait.checked_sub(elapsed) {
+        Some(remaining) if !remaining.is_zero() => Err(err(remaining)),
+        _ => Ok(()),
+    }
+}
+
+/// A key attempt let through by [`reserve`], counted as a wrong key until
+/// it is settled by [`right_key`], [`wrong_key`] or [`release`].
+#[must_use]
+pub struct Attempt {
+    paste_id: i32,
+    /// The paste's wrong keys, this one included.
+    failures: i32,
+    burn_after: Option<i32>,
+    /// The client charged for the attempt, if any.
+    client: Option<String>,
+    /// When the attempt was counted, as the paste's last wrong key.
+    reserved_at: DateTime<Utc>,
+    /// The paste's last wrong key before this attempt, for [`release`] to
+    /// put back.
+    previous_failure: Option<DateTime<Utc>>,
+}
+
+/// Count a key attempt on the paste `key` from `client` before it is tried,
+/// or turn it away if either has had too many wrong keys lately.  Errors
+/// charging the client let the attempt through.
+pub async fn reserve(
+    pool: &common::db::DbPool,
+    config: &Config,
+    key: &str,
+    paste_id: i32,
+    client: Option<&str>,
+) -> Result<Attempt, PasteError> {
+    let mut charged = None;
+    if let Some(client) = client.filter(|_| config.key_failures_per_hour > 0) {
+        let limit = Limit::per_hour(config.key_failures_per_hour);
+        match ratelimit::take(pool, client, CLIENT_BUCKET, limit, 1.0).await {
+            Ok(None) => charged = Some(client.to_string()),
+            Ok(Some(wait)) => {
+                warn!("Refused key attempt on paste {key} from {client}: too many wrong keys");
+                METRICS
+                    .key_attempts_refused
+                    .with_label_values(&["client"])
+                    .inc();
+                return Err(PasteError::TooManyAttempts(wait));
+            }
+            Err(e) => error!("Error counting key attempt for {client}: {e}"),
+        }
+    }
+
+    // The same rules as check_paste, applied to the row as it is once
+    // locked; the old timestamp comes back for release.
+    let now = Utc::now();
+    type Reserved = (i32, Option<i32>, Option<DateTime<Utc>>);
+    let reserved: Result<Option<Reserved>, _> = sqlx::query_as(
+        "WITH old AS (SELECT id, date_key_failed FROM pastes WHERE id = $1 FOR UPDATE)
+         UPDATE pastes p SET key_failures = p.key_failures + 1, date_key_failed = $2
+         FROM old
+         WHERE p.id = old.id AND (
+             p.date_key_failed IS NULL
+             OR p.date_key_failed + make_interval(secs => CASE
+                 WHEN $3 > 0 AND p.key_failures >= $3 THEN $4
+                 WHEN p.key_failures <= $5 THEN 0
+                 ELSE LEAST($6 * power(2, LEAST(p.key_failures - $5 - 1, 16)), $7)
+             END) <= $2)
+         RETURNING p.key_failures, p.burn_after_key_failures, old.date_key_failed",
+    )
+    .bind(paste_id)
+    .bind(now)
+    .bind(config.key_lockout_failures)
+    .bind(config.key_lockout_seconds as f64)
+    .bind(FREE_FAILURES)
+    .bind(DELAY_BASE.as_secs_f64())
+    .bind(DELAY_MAX.as_secs_f64())
+    .fetch_optional(pool)
+    .await;
+    let refused = match reserved {
+        Ok(Some((failures, burn_after, previous_failure))) => {
+            return Ok(Attempt {
+                paste_id,
+                failures,
+                burn_after,
+                client: charged,
+                reserved_at: now,
+                previous_failure,
+            })
+        }
+        Ok(None) => refusal(pool, config, paste_id, now).await,
+        Err(e) => e.into(),
+    };
+    refund(pool, config, charged.as_deref()).await;
+    let reason = match refused {
+        PasteError::TooManyAttempts(_) => Some("delay"),
+        PasteError::Locked(_) => Some("locked"),
+        _ => None,
+    };
+    if let Some(reason) = reason {
+        warn!("Refused key attempt on paste {key} from {client:?} after too many wrong keys");
+        METRICS
+            .key_attempts_refused
+            .with_label_values(&[reason])
+            .inc();
+    }
+    Err(refused)
+}
+
+/// Why [`reserve`] couldn't count an attempt on the paste: it is delayed or
+/// locked, or gone.
+async fn refusal(
+    pool: &common::db::DbPool,
+    config: &Config,
+    paste_id: i32,
+    now: DateTime<Utc>,
+) -> PasteError {
+    let row: Option<(i32, Option<DateTime<Utc>>)> =
+        match sqlx::query_as("SELECT key_failures, date_key_failed FROM pastes WHERE id = $1")
+            .bind(paste_id)
+            .fetch_optional(pool)
+            .await
+        {
+            Ok(row) => row,
+            Err(e) => return e.into(),
+        };
+    let Some((failures, last_failure)) = row else {
+        return PasteError::NotFound;
+    };
+    match check_paste(
+        failures,
+        last_failure,
+        config.key_lockout_failures,
+        Duration::from_secs(config.key_lockout_seconds),
+        now,
+    ) {
+        Err(e) => e,
+        // The row changed after the update looked at it.
+        Ok(()) => PasteError::TooManyAttempts(DELAY_BASE),
+    }
+}
+
+/// Give the client back the token [`reserve`] took.
+async fn refund(pool: &common::db::DbPool, config: &Config, client: Option<&str>) {
+    let Some(client) = client else {
+        return;
+    };
+    let limit = Limit::per_hour(config.key_failures_per_hour);
+    if let Err(e) = ratelimit::refund(pool, client, CLIENT_BUCKET, limit, 1.0).await {
+        error!("Error refunding key attempt for {client}: {e}");
+    }
+}
+
+/// The key was right: forget the paste's wrong keys and refund the client.
+pub async fn right_key(pool: &common::db::DbPool, config: &Config, attempt: Attempt) {
+    reset(pool, attempt.paste_id).await;
+    refund(pool, config, attempt.client.as_deref()).await;
+}
+
+/// The attempt never got as far as trying the key, or failed for some other
+/// reason: take it back, along with its timestamp unless a later attempt
+/// has replaced it, so it doesn't restart the paste's delay or lockout.
+pub async fn release(pool: &common::db::DbPool, config: &Config, attempt: Attempt) {
+    if let Err(e) = sqlx::query(
+        "UPDATE pastes SET key_failures = GREATEST(key_failures - 1, 0),
+             date_key_failed = CASE WHEN date_key_failed = $2 THEN $3 ELSE date_key_failed END
+         WHERE id = $1",
+    )
+    .bind(attempt.paste_id)
+    .bind(attempt.reserved_at)
+    .bind(attempt.previous_failure)
+    .execute(pool)
+    .await
+    {
+        error!(
+            "Error releasing key attempt on paste id={}: {e}",
+            attempt.paste_id
+        );
+    }
+    refund(pool, config, attempt.client.as_deref()).await;
+}
+
+/// The key was wrong, and stays counted as of now: burn the paste in `req`
+/// if that reaches its `burn_after_key_failures`.  Failures are logged rather than
+/// returned: the caller is reporting the wrong key either way.
+pub async fn wrong_key(
+    pool: &common::db::DbPool,
+    store: &dyn BlobStore,
+    key: &str,
+    attempt: Attempt,
+    req: &DeletionRequest,
+) {
+    let failures = attempt.failures;
+    warn!(
+        "Wrong key for paste {key} from {:?} ({failures} wrong keys)",
+        attempt.client
+    );
+    // The wait runs from when the key was found wrong, not from when the
+    // slow key derivation started.
+    if let Err(e) = sqlx::query(
+        "UPDATE pastes SET date_key_failed = GREATEST(date_key_failed, $2) WHERE id = $1",
+    )
+    .bind(attempt.paste_id)
+    .bind(Utc::now())
+    .execute(pool)
+    .await
+    {
+        error!("Error recording wrong key on paste id={}: {e}", req.id);
+    }
+    if attempt.burn_after.is_some_and(|n| failures >= n) {
+        warn!("Burning paste {key} after {failures} wrong keys");
+        if let Err(e) = burn(pool, store, req).await {
+            warn!(
+                "Failed to delete paste id={} burned after wrong keys: {e}",
+                req.id
+            );
+        }
+    }
+}
+
+/// Mark the paste burned, so nothing reads it again, and delete it; on
+/// failure the sweeper picks the burned row up later.
+async fn burn(
+    pool: &common::db::DbPool,
+    store: &dyn BlobStore,
+    req: &DeletionRequest,
+) -> anyhow::Result<()> {
+    let claimed =
+        sqlx::query("UPDATE pastes SET date_burned = $1 WHERE id = $2 AND date_burned IS NULL")
+            .bind(Utc::now())
+            .bind(req.id)
+            .execute(pool)
+            .await?
+            .rows_affected();
+    if claimed == 0 {
+        return Ok(());
+    }
+    METRICS.key_failure_burns.inc();
+    Paste::attempt_deletion(pool, store, req).await
+}
+
+/// Forget the paste's wrong keys after the right one was given.
+async fn reset(pool: &common::db::DbPool, paste_id: i32) {
+    if let Err(e) = sqlx::query("UPDATE pastes SET key_failures = 0 WHERE id = $1")
+        .bind(paste_id)
+        .execute(pool)
+        .await
+    {
+        error!("Error resetting wrong keys for paste id={paste_id}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKOUT: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn delay_starts_after_the_free_failures_and_doubles() {
+        assert_eq!(delay(0), Duration::ZERO);
+        assert_eq!(delay(FREE_FAILURES), Duration::ZERO);
+        assert_eq!(delay(FREE_FAILURES + 1), Duration::from_secs(1));
+        assert_eq!(delay(FREE_FAILURES + 3), Duration::from_secs(4));
+        assert_eq!(delay(1000), DELAY_MAX);
+    }
+
+    #[test]
+    fn paste_is_delayed_then_locked() {
+        let now = Utc::now();
+        let just_now = Some(now - chrono::Duration::milliseconds(100));
+        assert!(check_paste(0, None, 10, LOCKOUT, now).is_ok());
+        assert!(check_paste(FREE_FAILURES, just_now, 10, LOCKOUT, now).is_ok());
+        assert!(matches!(
+            check_paste(FREE_FAILURES + 2, just_now, 10, LOCKOUT, now),
+            Err(PasteError::TooManyAttempts(_))
+        ));
+        let a_minute_ago = Some(now - chrono::Duration::minutes(1));
+        assert!(check_paste(FREE_FAILURES + 2, a_minute_ago, 10, LOCKOUT, now).is_ok());
+        assert!(matches!(
+            check_paste(10, a_minute_ago, 10, LOCKOUT, now),
+            Err(PasteError::Locked(wait)) if wait == Duration::from_secs(59 * 60)
+        ));
+        let two_hours_ago = Some(now - chrono::Duration::hours(2));
+        assert!(check_paste(10, two_hours_ago, 10, LOCKOUT, now).is_ok());
+    }
+
+    #[test]
+    fn zero_lockout_failures_disables_the_lockout() {
+        let now = Utc::now();
+        let a_minute_ago = Some(now - chrono::Duration::minutes(1));
+        assert!(matches!(
+            check_paste(50, a_minute_ago, 0, LOCKOUT, now),
+            Err(PasteError::TooManyAttempts(_))
+        ));
+    }
+}
@@ -1,12 +1,25 @@
+pub mod accounts;
+pub mod api;
 pub mod config;
+pub mod deletion;
+pub mod detect;
+pub mod error;
 pub mod handlers;
+pub mod highlight;
+pub mod keyguard;
+pub mod metrics;
+pub mod mime;
 pub mod models;
+pub mod ratelimit;
+pub mod reconcile;
 pub mod service;
 pub mod storage;
 pub mod test_utils;
+pub mod upload;
 
-pub use config::Config;
+pub use config::{Config, StorageBackend};
 use std::sync::Arc;
+use std::time::Instant;
 use tera::Tera;
 
 pub type State = Arc<Resources>;
@@ -15,9 +28,10 @@ pub struct Resources {
     pub tera: Tera,
     pub db: common::db::DbPool,
     pub config: Config,
-    pub s3: aws_sdk_s3::Client,
-    /// Channel used to enqueue pastes for background deletion.
-    pub deletion_tx: tokio::sync::mpsc::Sender<models::DeletionRequest>,
+    /// Blob storage for encrypted paste content.
+    pub store: Arc<dyn storage::BlobStore>,
+    /// When `/metrics` last recomputed its database-backed gauges.
+    pub gauges_refreshed: tokio::sync::Mutex<Option<Instant>>,
 }
 
 impl Resources {
@@ -25,15 +39,14 @@ impl Resources {
         tera: Tera,
         db: common::db::DbPool,
         config: Config,
-        s3: aws_sdk_s3::Client,
-        deletion_tx: tokio::sync::mpsc::Sender<models::DeletionRequest>,
+        store: Arc<dyn storage::BlobStore>,
     ) -> Self {
         Self {
             tera,
             db,
             config,
-            s3,
-            deletion_tx,
+            store,
+            gauges_refreshed: tokio::sync::Mutex::new(None),
         }
     }
 }
@@ -0,0 +1,169 @@
+//! Prometheus metrics, served at `/metrics`.
+//!
+//! Everything registers into one process-wide registry, [`METRICS`], so the
+//! background tasks and the blob store can record without being handed
+//! state.  Gauges that come from the database are refreshed when scraped, see [`crate::handlers::metrics`].
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::LazyLock;
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Blob store latency buckets, in seconds: from local disk to slow S3.
+const STORAGE_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+pub struct Metrics {
+    registry: Registry,
+    pub pastes_created: IntCounter,
+    pub pastes_read: IntCounter,
+    /// By `reason`: `"key"` (missing or wrong key) or `"signature"`.
+    pub decrypt_failures: IntCounterVec,
+    /// Reads with a user key turned away before decrypting, by `reason`:
+    /// `"delay"` or `"locked"` (too many wrong keys for the paste) or
+    /// `"client"` (too many from the client), see [`crate::keyguard`].
+    pub key_attempts_refused: IntCounterVec,
+    /// Pastes burned after too many wrong keys.
+    pub key_failure_burns: IntCounter,
+    /// By `backend`, `op` (`get` / `put` / `delete`) and `result`.
+    pub storage_seconds: HistogramVec,
+    /// Deletion jobs waiting, including ones backing off after a failure.
+    pub deletion_queue_depth: IntGauge,
+    /// Deletion jobs that failed too often and were given up on.
+    pub deletion_dead_letters: IntGauge,
+    /// By `outcome`: `"committed"` or `"rolled_back"`.
+    pub deletions: IntCounterVec,
+    /// Sweeper ticks by `result`: `"acquired"` or `"contended"` (another
+    /// instance held the lock).
+    pub sweeper_lock: IntCounterVec,
+    pub stored_bytes: IntGauge,
+    /// Revisions whose blob size predates its being recorded, so aren't in
+    /// `stored_bytes`.
+    pub stored_blobs_unsized: IntGauge,
+    /// Revisions [`crate::reconcile`] found without a blob in the store.
+    pub blobs_missing: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("paste".to_string()), None).expect("valid metrics registry");
+        let metrics = Self {
+            pastes_created: IntCounter::new("created_total", "Pastes created").unwrap(),
+            pastes_read: IntCounter::new("reads_total", "Successful paste reads").unwrap(),
+            decrypt_failures: IntCounterVec::new(
+                Opts::new("decrypt_failures_total", "Blobs that failed to decrypt"),
+                &["reason"],
+            )
+            .unwrap(),
+            key_attempts_refused: IntCounterVec::new(
+                Opts::new(
+                    "key_attempts_refused_total",
+                    "User key attempts refused after too many wrong keys",
+                ),
+                &["reason"],
+            )
+            .unwrap(),
+            key_failure_burns: IntCounter::new(
+                "key_failure_burns_total",
+                "Pastes burned after too many wrong keys",
+            )
+            .unwrap(),
+            storage_seconds: HistogramVec::new(
+                HistogramOpts::new("storage_seconds", "Blob store operation latency")
+                    .buckets(STORAGE_BUCKETS.to_vec()),
+                &["backend", "op", "result"],
+            )
+            .unwrap(),
+            deletion_queue_depth: IntGauge::new(
+                "deletion_queue_depth",
+                "Deletion jobs waiting to run",
+            )
+            .unwrap(),
+            deletion_dead_letters: IntGauge::new(
+                "deletion_dead_letters",
+                "Deletion jobs given up after repeated failures",
+            )
+            .unwrap(),
+            deletions: IntCounterVec::new(
+                Opts::new("deletions_total", "Paste deletion attempts"),
+                &["outcome"],
+            )
+            .unwrap(),
+            sweeper_lock: IntCounterVec::new(
+                Opts::new(
+                    "sweeper_lock_total",
+                    "Sweeper ticks by advisory lock result",
+                ),
+                &["result"],
+            )
+            .unwrap(),
+            stored_bytes: IntGauge::new("stored_bytes", "Bytes of paste blobs stored").unwrap(),
+            stored_blobs_unsized: IntGauge::new(
+                "stored_blobs_unsized",
+                "Stored blobs of unrecorded size",
+            )
+            .unwrap(),
+            blobs_missing: IntGauge::new(
+                "blobs_missing",
+                "Revisions whose blob is missing from the store",
+            )
+            .unwrap(),
+            registry,
+        };
+        let collectors: [Box<dyn prometheus::core::Collector>; 13] = [
+            Box::new(metrics.pastes_created.clone()),
+            Box::new(metrics.pastes_read.clone()),
+            Box::new(metrics.decrypt_failures.clone()),
+            Box::new(metrics.key_attempts_refused.clone()),
+            Box::new(metrics.key_failure_burns.clone()),
+            Box::new(metrics.storage_seconds.clone()),
+            Box::new(metrics.deletion_queue_depth.clone()),
+            Box::new(metrics.deletion_dead_letters.clone()),
+            Box::new(metrics.deletions.clone()),
+            Box::new(metrics.sweeper_lock.clone()),
+            Box::new(metrics.stored_bytes.clone()),
+            Box::new(metrics.stored_blobs_unsized.clone()),
+            Box::new(metrics.blobs_missing.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("unique metric names");
+        }
+        metrics
+    }
+
+    /// Everything in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buf) {
+            tracing::error!("Error encoding metrics: {e}");
+        }
+        String::from_utf8(buf).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_prefixed_metrics() {
+        METRICS.pastes_created.inc();
+        METRICS
+            .storage_seconds
+            .with_label_values(&["memory", "get", "ok"])
+            .observe(0.002);
+        let text = METRICS.render();
+        assert!(text.contains("paste_created_total"));
+        assert!(text
+            .contains("paste_storage_seconds_bucket{backend=\"memory\",op=\"get\",result=\"ok\""));
+        assert!(text.contains("# TYPE paste_deletion_queue_depth gauge"));
+    }
+}
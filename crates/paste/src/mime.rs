@@ -0,0 +1,109 @@
+//! MIME types and file names for raw paste responses.
+
+use crate::detect;
+use crate::models::TEXT_MIME_TYPE;
+
+/// Paste types with a standard MIME type that browsers display.  Every
+/// other type is served as [`TEXT_MIME_TYPE`].
+const TYPE_MIME_TYPES: &[(&str, &str)] = &[
+    ("css", "text/css; charset=utf-8"),
+    ("html", "text/html; charset=utf-8"),
+    ("javascript", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("jsx", "text/javascript; charset=utf-8"),
+    ("svg", "image/svg+xml"),
+    ("xml", "text/xml; charset=utf-8"),
+];
+
+/// Download extensions where the first match in
+/// [`detect::EXTENSIONS`] isn't the usual one.
+const PREFERRED_EXTENSIONS: &[(&str, &str)] = &[
+    ("c_cpp", "cpp"),
+    ("markdown", "md"),
+    ("objectivec", "m"),
+    ("text", "txt"),
+    ("yaml", "yaml"),
+];
+
+/// Extensions for common binary MIME types.
+const MIME_EXTENSIONS: &[(&str, &str)] = &[
+    ("application/gzip", "gz"),
+    ("application/json", "json"),
+    ("application/pdf", "pdf"),
+    ("application/zip", "zip"),
+    ("image/gif", "gif"),
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/svg+xml", "svg"),
+    ("image/webp", "webp"),
+    ("text/html", "html"),
+];
+
+/// `Content-Security-Policy` for raw responses: no script, no subresources
+/// beyond inline styles, and a sandbox giving the document an opaque
+/// origin, so an HTML or SVG paste can't act on ours.
+pub const RAW_CSP: &str = "default-src 'none'; style-src 'unsafe-inline'; sandbox";
+
+/// `Content-Type` for a raw response.  `mime_type` is the recorded upload
+/// type; when that's the generic [`TEXT_MIME_TYPE`], the paste type picks a
+/// more specific one where there is one.
+pub fn raw_content_type(content_type: &str, mime_type: &str) -> String {
+    if mime_type != TEXT_MIME_TYPE {
+        return mime_type.to_string();
+    }
+    TYPE_MIME_TYPES
+        .iter()
+        .find(|(t, _)| *t == content_type)
+        .map_or(TEXT_MIME_TYPE, |(_, m)| *m)
+        .to_string()
+}
+
+/// File extension (without the dot) for a download of content with this
+/// paste type and recorded MIME type.
+pub fn extension(content_type: &str, mime_type: &str) -> &'static str {
+    let essence = mime_type.split(';').next().unwrap_or("").trim();
+    if !essence.starts_with("text/") {
+        return MIME_EXTENSIONS
+            .iter()
+            .find(|(m, _)| essence.eq_ignore_ascii_case(m))
+            .map_or("bin", |(_, ext)| *ext);
+    }
+    PREFERRED_EXTENSIONS
+        .iter()
+        .copied()
+        .chain(detect::EXTENSIONS.iter().map(|&(ext, t)| (t, ext)))
+        .find(|&(t, _)| t == content_type)
+        .map_or("txt", |(_, ext)| ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::BINARY_MIME_TYPE;
+
+    #[test]
+    fn text_uploads_use_the_paste_type() {
+        assert_eq!(
+            raw_content_type("html", TEXT_MIME_TYPE),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(raw_content_type("json", TEXT_MIME_TYPE), "application/json");
+        assert_eq!(raw_content_type("rust", TEXT_MIME_TYPE), TEXT_MIME_TYPE);
+    }
+
+    #[test]
+    fn declared_mime_types_win() {
+        assert_eq!(raw_content_type("text", "image/png"), "image/png");
+        assert_eq!(raw_content_type("html", BINARY_MIME_TYPE), BINARY_MIME_TYPE);
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(extension("rust", TEXT_MIME_TYPE), "rs");
+        assert_eq!(extension("c_cpp", TEXT_MIME_TYPE), "cpp");
+        assert_eq!(extension("text", TEXT_MIME_TYPE), "txt");
+        assert_eq!(extension("cobol", TEXT_MIME_TYPE), "txt");
+        assert_eq!(extension("text", "image/png"), "png");
+        assert_eq!(extension("text", BINARY_MIME_TYPE), "bin");
+    }
+}
@@ -1,21 +1,46 @@
 use chrono::{DateTime, Duration, Utc};
 use rand::distr::Alphanumeric;
 use rand::RngExt;
-use sqlx::FromRow;
-use tokio::sync::mpsc;
+use sqlx::{Connection, FromRow};
 use tracing::{error, info, warn};
 
-use crate::storage::{self, BlobHeaderV1};
+use crate::error::PasteError;
+use crate::keyguard;
+use crate::metrics::METRICS;
+use crate::storage::{
+    self, BlobHeaderDecrypt, BlobHeaderV2, BlobHeaderV3, BlobHeaderV4, BlobStore, ContentEncoding,
+    ObjectNotFound, VersionedHeader,
+};
 use crate::Config;
 
 // ---------------------------------------------------------------------------
 // Constants
 // ---------------------------------------------------------------------------
 
+/// MIME type recorded for uploads that are valid UTF-8 but didn't declare a
+/// more specific type.
+pub const TEXT_MIME_TYPE: &str = "text/plain; charset=utf-8";
+
+/// MIME type recorded for non-UTF-8 uploads that didn't declare a type.
+pub const BINARY_MIME_TYPE: &str = "application/octet-stream";
+
+/// Most files a multi-file paste may hold.
+pub const MAX_PASTE_FILES: usize = 32;
+
+/// Most entries a client-encrypted paste's [`ClientParams`] may hold.
+const MAX_CLIENT_PARAMS: usize = 16;
+
+/// Longest name or value allowed in [`ClientParams`].
+const MAX_CLIENT_PARAM_LEN: usize = 1024;
+
 /// After this many days the bucket lifecycle policy has already deleted the S3
 /// object, so the DB row can be committed without a successful S3 delete.
 const S3_BUCKET_TTL_DAYS: i64 = 30;
 
+/// A revision whose blob failed to re-encrypt isn't tried again for this many
+/// hours, see [`Paste::reencrypt_batch`].
+const REENCRYPT_RETRY_HOURS: i64 = 24;
+
 // ---------------------------------------------------------------------------
 // Key helpers
 // ---------------------------------------------------------------------------
@@ -30,6 +55,24 @@ fn gen_key(n_chars: usize) -> String {
         .collect::<String>()
 }
 
+/// Length of the secret deletion / edit tokens returned to a paste's creator.
+const OWNER_TOKEN_CHARS: usize = 32;
+
+/// Generate a new random deletion or edit token.  Only its HMAC is stored,
+/// see [`hash_owner_token`].
+pub fn new_owner_token() -> String {
+    rand::rng()
+        .sample_iter(Alphanumeric)
+        .take(OWNER_TOKEN_CHARS)
+        .map(char::from)
+        .collect()
+}
+
+/// HMAC of a deletion or edit token, as stored in the `pastes` row.
+fn hash_owner_token(token: &str, config: &Config) -> String {
+    common::crypto::hmac_sign(token, config.signing_key.as_bytes())
+}
+
 /// Create a new paste.key, making sure it isn't already in use
 async fn get_new_key(pool: &common::db::DbPool) -> anyhow::Result<String> {
     let mut n_chars = 5;
@@ -41,16 +84,48 @@ async fn get_new_key(pool: &common::db::DbPool) -> anyhow::Result<String> {
     Ok(new_key)
 }
 
+/// Storage key of revision `rev` of the paste at `key`.  Revision 1 is
+/// stored under the paste key itself.
+fn revision_storage_uri(key: &str, rev: i32) -> String {
+    if rev == 1 {
+        key.to_string()
+    } else {
+        format!("{key}-r{rev}")
+    }
+}
+
+/// AAD binding a blob to revision `rev` of paste row `id`.  Revision 1 uses
+/// the bare row id, which is what pastes were encrypted with before they
+/// could be edited.
+fn blob_aad(id: i32, rev: i32) -> Vec<u8> {
+    let mut aad = id.to_be_bytes().to_vec();
+    if rev != 1 {
+        aad.extend_from_slice(&rev.to_be_bytes());
+    }
+    aad
+}
+
 // ---------------------------------------------------------------------------
-// Internal DB row (no content column — content lives in S3)
+// Internal DB row (no content column — content lives in the blob store)
 // ---------------------------------------------------------------------------
 
+/// Columns selected into [`PasteRow`].
+const PASTE_ROW_COLUMNS: &str = "id, key, storage_uri, content_type, mime_type, \
+     date_created, date_viewed, exp_date, date_queued, burn_after_reading, view_count, max_views, \
+     current_rev, (SELECT p.key FROM pastes p WHERE p.id = pastes.parent_id) AS parent_key";
+
+/// SQL predicate matching pastes that can still be read: not burned and not
+/// out of views.  Expiry is checked separately so expired rows get cleaned up.
+pub(crate) const READABLE: &str =
+    "date_burned IS NULL AND (max_views IS NULL OR view_count < max_views)";
+
 #[derive(Debug, FromRow)]
 struct PasteRow {
     pub id: i32,
     pub key: String,
     pub storage_uri: String,
     pub content_type: String,
+    pub mime_type: Option<String>,
     pub date_created: DateTime<Utc>,
     pub date_viewed: DateTime<Utc>,
     pub exp_date: Option<DateTime<Utc>>,
@@ -59,32 +134,376 @@ struct PasteRow {
     /// the value is not used in application logic.
     #[allow(dead_code)]
     pub date_queued: Option<DateTime<Utc>>,
+    pub burn_after_reading: bool,
+    pub view_count: i32,
+    pub max_views: Option<i32>,
+    pub current_rev: i32,
+    pub parent_key: Option<String>,
+}
+
+// ---------------------------------------------------------------------------
+// Blob helpers
+// ---------------------------------------------------------------------------
+
+/// `paste_revisions.key_id` marker for blobs encrypted with a user-supplied
+/// key, server- or client-side.  Server key ids can't contain ':', so it
+/// never collides with one.
+pub const USER_KEY_ID: &str = ":user";
+
+/// The `paste_revisions.key_id` recorded for a blob sealed by [`seal_blob`].
+fn sealed_key_id<'a>(
+    config: &'a Config,
+    user_encryption_key: Option<&str>,
+    client_params: Option<&ClientParams>,
+) -> &'a str {
+    match (user_encryption_key, client_params) {
+        (None, None) => &config.encryption_key.id,
+        _ => USER_KEY_ID,
+    }
+}
+
+/// The `paste_revisions.blob_version` recorded for a blob sealed by
+/// [`seal_blob`].
+fn sealed_blob_version(
+    user_encryption_key: Option<&str>,
+    client_params: Option<&ClientParams>,
+) -> i32 {
+    let version = match (user_encryption_key, client_params) {
+        (_, Some(_)) => BlobHeaderV3::VERSION,
+        (Some(_), None) => BlobHeaderV4::VERSION,
+        (None, None) => BlobHeaderV2::VERSION,
+    };
+    version as i32
+}
+
+/// Compress, encrypt and sign `content` — or, when `files` is non-empty, an
+/// archive of `files` — into a [`BlobHeaderV2`] blob, or with a user key, a
+/// [`BlobHeaderV4`] blob using [`Config::kdf`].  With `client_params`,
+/// `content` is already client-encrypted and is only signed, into a
+/// [`BlobHeaderV3`] blob.
+fn seal_blob(
+    content: &[u8],
+    files: &[PasteFile],
+    client_params: Option<&ClientParams>,
+    config: &Config,
+    user_encryption_key: Option<&str>,
+    aad: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let signing_key = config.signing_key.as_bytes();
+    if let Some(params) = client_params {
+        let (header, ciphertext) = BlobHeaderV3::seal(content, signing_key, params.clone());
+        return storage::encode_blob(&header, &ciphertext);
+    }
+    if let Some(user_key) = user_encryption_key.map(str::as_bytes) {
+        let (header, ciphertext) = if files.is_empty() {
+            BlobHeaderV4::encrypt(
+                content,
+                signing_key,
+                config.blob_compression,
+                user_key,
+                config.kdf,
+                aad,
+            )?
+        } else {
+            BlobHeaderV4::encrypt_archive(
+                files,
+                signing_key,
+                config.blob_compression,
+                user_key,
+                config.kdf,
+                aad,
+            )?
+        };
+        return storage::encode_blob(&header, &ciphertext);
+    }
+    let enc_key = config.encryption_key.as_key_ref();
+    let (header, ciphertext) = if files.is_empty() {
+        BlobHeaderV2::encrypt(
+            content,
+            signing_key,
+            config.blob_compression,
+            None,
+            enc_key,
+            aad,
+        )?
+    } else {
+        BlobHeaderV2::encrypt_archive(
+            files,
+            signing_key,
+            config.blob_compression,
+            None,
+            enc_key,
+            aad,
+        )?
+    };
+    storage::encode_blob(&header, &ciphertext)
+}
+
+/// [`seal_blob`], moved to the blocking pool when it derives a key from a
+/// user key: Argon2id takes long enough to stall an async worker.
+async fn seal_blob_blocking(
+    content: &[u8],
+    files: &[PasteFile],
+    client_params: Option<&ClientParams>,
+    config: &Config,
+    user_encryption_key: Option<&str>,
+    aad: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let (Some(user_key), None) = (user_encryption_key, client_params) else {
+        return seal_blob(
+            content,
+            files,
+            client_params,
+            config,
+            user_encryption_key,
+            aad,
+        );
+    };
+    let (content, files, config) = (content.to_vec(), files.to_vec(), config.clone());
+    let (user_key, aad) = (user_key.to_string(), aad.to_vec());
+    tokio::task::spawn_blocking(move || {
+        seal_blob(&content, &files, None, &config, Some(&user_key), &aad)
+    })
+    .await?
+}
+
+/// Fetch the blob at `storage_uri`.
+async fn fetch_blob(store: &dyn BlobStore, storage_uri: &str) -> Result<Vec<u8>, PasteError> {
+    match store.get(storage_uri).await {
+        Ok(blob) => Ok(blob),
+        Err(e) if e.is::<ObjectNotFound>() => {
+            warn!("Blob {storage_uri:?} is missing from the store");
+            Err(PasteError::NotFound)
+        }
+        Err(e) => Err(PasteError::StorageUnavailable(e)),
+    }
+}
+
+/// Fetch, decrypt and verify a revision of the paste in `row`.  A user key
+/// tried against a user-key blob goes through [`crate::keyguard`]: it is
+/// counted before the decrypt and settled by its outcome.
+#[allow(clippy::too_many_arguments)]
+async fn open_guarded(
+    pool: &common::db::DbPool,
+    store: &dyn BlobStore,
+    config: &Config,
+    row: &PasteRow,
+    storage_uri: &str,
+    rev: i32,
+    user_enc_key: Option<&str>,
+    client: Option<&str>,
+) -> Result<BlobContent, PasteError> {
+    let blob = fetch_blob(store, storage_uri).await?;
+    let (header, ciphertext) = storage::decode_blob(&blob).map_err(PasteError::Corrupt)?;
+    let attempt = match user_enc_key {
+        Some(_) if needs_user_key(header.as_ref()) => {
+            Some(keyguard::reserve(pool, config, &row.key, row.id, client).await?)
+        }
+        _ => None,
+    };
+    let aad = blob_aad(row.id, rev);
+    let opened = match (&attempt, user_enc_key) {
+        // Deriving the key from a user key runs Argon2id; keep it off the
+        // async workers.
+        (Some(_), Some(user_key)) => {
+            let (config, storage_uri) = (config.clone(), storage_uri.to_string());
+            let user_key = user_key.to_string();
+            tokio::task::spawn_blocking(move || {
+                decrypt_decoded(
+                    header.as_ref(),
+                    &ciphertext,
+                    &config,
+                    &storage_uri,
+                    &aad,
+                    Some(&user_key),
+                )
+            })
+            .await
+            .unwrap_or_else(|e| Err(PasteError::Internal(e.into())))
+        }
+        _ => decrypt_decoded(
+            header.as_ref(),
+            &ciphertext,
+            config,
+            storage_uri,
+            &aad,
+            user_enc_key,
+        ),
+    };
+    if let Some(attempt) = attempt {
+        match &opened {
+            Ok(_) => keyguard::right_key(pool, config, attempt).await,
+            Err(PasteError::WrongKey) => {
+                let req = DeletionRequest {
+                    id: row.id,
+                    storage_uri: row.storage_uri.clone(),
+                    date_created: row.date_created,
+                };
+                keyguard::wrong_key(pool, store, &row.key, attempt, &req).await;
+            }
+            Err(_) => keyguard::release(pool, config, attempt).await,
+        }
+    }
+    opened
+}
+
+/// What a blob holds once decrypted, see [`decrypt_blob`].
+struct BlobContent {
+    /// The content; for multi-file pastes, that of the first file.
+    content: Vec<u8>,
+    /// Files of a multi-file paste; empty otherwise.
+    files: Vec<PasteFile>,
+    /// Set for client-encrypted blobs, whose `content` is then ciphertext.
+    client_params: Option<ClientParams>,
+}
+
+/// Decrypt and verify `blob`, trying every key in the configured key ring.
+fn decrypt_blob(
+    blob: &[u8],
+    config: &Config,
+    storage_uri: &str,
+    aad: &[u8],
+    user_enc_key: Option<&str>,
+) -> Result<BlobContent, PasteError> {
+    let (header, ciphertext) = storage::decode_blob(blob).map_err(PasteError::Corrupt)?;
+    decrypt_decoded(
+        header.as_ref(),
+        &ciphertext,
+        config,
+        storage_uri,
+        aad,
+        user_enc_key,
+    )
+}
+
+/// Whether the blob was sealed with a user key on the server, so opening it
+/// takes that key.
+fn needs_user_key(header: &dyn BlobHeaderDecrypt) -> bool {
+    header.server_key_id().is_none() && header.client_params().is_none()
+}
+
+/// [`decrypt_blob`] for a blob already split by [`storage::decode_blob`].
+fn decrypt_decoded(
+    header: &dyn BlobHeaderDecrypt,
+    ciphertext: &[u8],
+    config: &Config,
+    storage_uri: &str,
+    aad: &[u8],
+    user_enc_key: Option<&str>,
+) -> Result<BlobContent, PasteError> {
+    let needs_user_key = needs_user_key(header);
+    if needs_user_key && user_enc_key.is_none() {
+        METRICS.decrypt_failures.with_label_values(&["key"]).inc();
+        return Err(PasteError::KeyRequired);
+    }
+
+    // Decrypt — the header version drives the decryption logic.
+    let plaintext_bytes = header
+        .decrypt(
+            ciphertext,
+            user_enc_key.map(|k| k.as_bytes()),
+            &config.key_ring(),
+            aad,
+        )
+        .map_err(|e| {
+            METRICS.decrypt_failures.with_label_values(&["key"]).inc();
+            if needs_user_key {
+                PasteError::WrongKey
+            } else {
+                PasteError::Corrupt(e.context(format!("blob {storage_uri:?}")))
+            }
+        })?;
+
+    // Verify HMAC signature from the blob header.
+    if !common::crypto::hmac_verify_bytes(
+        &plaintext_bytes,
+        header.sig(),
+        config.signing_key.as_bytes(),
+    ) {
+        error!("HMAC verification failed for blob {storage_uri:?}");
+        METRICS
+            .decrypt_failures
+            .with_label_values(&["signature"])
+            .inc();
+        return Err(PasteError::Corrupt(anyhow::anyhow!(
+            "signature mismatch in blob {storage_uri:?}"
+        )));
+    }
+
+    let client_params = header.client_params().cloned();
+    if header.content_encoding() != Some(ContentEncoding::Files) {
+        return Ok(BlobContent {
+            content: plaintext_bytes,
+            files: Vec::new(),
+            client_params,
+        });
+    }
+    let files = storage::decode_archive(&plaintext_bytes).map_err(PasteError::Corrupt)?;
+    let content = files.first().map(|f| f.content.clone()).unwrap_or_default();
+    Ok(BlobContent {
+        content,
+        files,
+        client_params,
+    })
 }
 
 // ---------------------------------------------------------------------------
 // Public structs
 // ---------------------------------------------------------------------------
 
+pub use crate::storage::{ClientParams, PasteFile};
+
+#[derive(Default)]
 pub struct NewPaste {
-    pub content: String,
+    pub content: Vec<u8>,
     pub content_type: String,
+    /// MIME type of `content`, see [`resolve_mime_type`].
+    pub mime_type: String,
+    /// Delete the paste after its first successful read.
+    pub burn_after_reading: bool,
+    /// Delete the paste once it has been read this many times.
+    pub max_views: Option<i32>,
+    /// Secret that lets the creator delete the paste early, see
+    /// [`new_owner_token`].  Stored HMAC'd.
+    pub deletion_token: String,
+    /// Secret that lets the creator publish new revisions, see
+    /// [`NewRevision::publish`].  `None` makes the paste read-only.
+    pub edit_token: Option<String>,
+    /// Row id of the paste this one was forked from.
+    pub parent_id: Option<i32>,
+    /// Named files.  When non-empty the paste holds these instead of
+    /// `content`, and `content_type` / `mime_type` should be the first
+    /// file's.
+    pub files: Vec<PasteFile>,
+    /// Set when `content` was encrypted by the uploader; the server stores
+    /// it as-is alongside these parameters, see [`BlobHeaderV3`].
+    pub client_params: Option<ClientParams>,
+    /// Account that created the paste, see [`crate::accounts`].
+    pub owner_id: Option<i32>,
+    /// Burn the paste after this many wrong keys, see [`crate::keyguard`].
+    pub burn_after_key_failures: Option<i32>,
 }
 
 impl NewPaste {
     /// Insert a new paste.
     ///
-    /// Content is **always** encrypted before being written to S3:
+    /// Content is **always** encrypted before being written to the blob store:
     /// - If `user_encryption_key` is `Some`, the user's password is used
-    ///   (PBKDF2-HMAC-SHA512 key derivation + fresh salt stored in the blob).
+    ///   (key derived by `config.kdf` from a fresh salt, both recorded in a
+    ///   [`BlobHeaderV4`]).
     /// - Otherwise the server's `config.encryption_key` is used directly
     ///   (SHA-256 of the config value → 32-byte AES key, no extra salt).
     ///
+    /// Content is compressed with `config.blob_compression` first and stored
+    /// as a [`BlobHeaderV2`] blob, or [`BlobHeaderV4`] with a user key.
+    ///
     /// The database row ID is included as AES-GCM Additional Authenticated
     /// Data (AAD) so the ciphertext is cryptographically bound to this row.
+    /// The content becomes revision 1 in `paste_revisions`.
     pub async fn insert(
         self,
         pool: &common::db::DbPool,
-        s3: &aws_sdk_s3::Client,
+        store: &dyn BlobStore,
         config: &Config,
         ttl_seconds: Option<u32>,
         user_encryption_key: Option<&str>,
@@ -102,51 +521,101 @@ impl NewPaste {
 
         // Insert the DB row inside the transaction to obtain the auto-generated
         // `id`, which we use as AAD.  The row is not visible to other readers
-        // until we commit (after S3 succeeds).
-        let row = sqlx::query_as::<_, PasteRow>(
-            "INSERT INTO pastes (key, storage_uri, content_type, date_created, date_viewed, exp_date)
-             VALUES ($1, $2, $3, $4, $5, $6)
-             RETURNING id, key, storage_uri, content_type, date_created, date_viewed, exp_date, date_queued",
-        )
+        // until we commit (after the upload succeeds).
+        let row = sqlx::query_as::<_, PasteRow>(&format!(
+            "INSERT INTO pastes (key, storage_uri, content_type, mime_type, date_created, date_viewed, exp_date, burn_after_reading, max_views, deletion_token_hash, edit_token_hash, parent_id, owner_id, burn_after_key_failures)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+             RETURNING {PASTE_ROW_COLUMNS}",
+        ))
         .bind(&key)
         .bind(&key) // storage_uri == paste key
         .bind(&self.content_type)
+        .bind(&self.mime_type)
         .bind(now)
         .bind(now)
         .bind(exp_date)
+        .bind(self.burn_after_reading)
+        .bind(self.max_views)
+        .bind(hash_owner_token(&self.deletion_token, config))
+        .bind(
+            self.edit_token
+                .as_deref()
+                .map(|t| hash_owner_token(t, config)),
+        )
+        .bind(self.parent_id)
+        .bind(self.owner_id)
+        .bind(self.burn_after_key_failures)
         .fetch_one(&mut *tx)
         .await?;
 
-        // AAD = big-endian bytes of the row id.
-        let aad = row.id.to_be_bytes();
-
-        // Encrypt content, compute HMAC signature, and build the blob.
-        let enc_key = config.encryption_key.as_key_ref();
-        let (header, ciphertext) = BlobHeaderV1::encrypt(
-            self.content.as_bytes(),
-            config.signing_key.as_bytes(),
-            user_encryption_key.map(|k| k.as_bytes()),
-            enc_key,
-            &aad,
-        )?;
+        // Compress and encrypt content, compute HMAC signature, and build the
+        // blob.  AAD = big-endian bytes of the row id.
+        let content_bytes = match self.files.as_slice() {
+            [] => self.content.len(),
+            files => files.iter().map(|f| f.content.len()).sum(),
+        };
+        let blob = seal_blob_blocking(
+            &self.content,
+            &self.files,
+            self.client_params.as_ref(),
+            config,
+            user_encryption_key,
+            &blob_aad(row.id, 1),
+        )
+        .await?;
 
-        let blob = storage::encode_blob(&header, &ciphertext)?;
+        sqlx::query(
+            "INSERT INTO paste_revisions (paste_id, rev, storage_uri, content_type, mime_type, date_created, key_id, blob_bytes, blob_version, content_bytes)
+             VALUES ($1, 1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(row.id)
+        .bind(&row.storage_uri)
+        .bind(&row.content_type)
+        .bind(&self.mime_type)
+        .bind(now)
+        .bind(sealed_key_id(
+            config,
+            user_encryption_key,
+            self.client_params.as_ref(),
+        ))
+        .bind(blob.len() as i64)
+        .bind(sealed_blob_version(
+            user_encryption_key,
+            self.client_params.as_ref(),
+        ))
+        .bind(content_bytes as i64)
+        .execute(&mut *tx)
+        .await?;
 
-        // Upload to S3.  On failure the transaction is dropped and auto-rolled
-        // back, so no DB row is committed.
-        storage::put_object(s3, &config.s3_bucket, &row.storage_uri, blob).await?;
+        // Upload the blob.  On failure the transaction is dropped and
+        // auto-rolled back, so no DB row is committed.
+        store.put(&row.storage_uri, blob).await?;
 
-        // S3 succeeded — commit the DB row.
+        // Upload succeeded — commit the DB row.
         tx.commit().await?;
+        METRICS.pastes_created.inc();
 
+        let content = match self.files.first() {
+            Some(first) => first.content.clone(),
+            None => self.content,
+        };
         Ok(Paste {
             id: row.id,
             key: row.key,
-            content: self.content,
+            content,
             content_type: row.content_type,
+            mime_type: self.mime_type,
             date_created: row.date_created,
             date_viewed: row.date_viewed,
             exp_date: row.exp_date,
+            burn_after_reading: row.burn_after_reading,
+            view_count: row.view_count,
+            max_views: row.max_views,
+            rev: 1,
+            current_rev: 1,
+            parent_key: row.parent_key,
+            files: self.files,
+            client_params: self.client_params,
         })
     }
 }
@@ -155,24 +624,244 @@ impl NewPaste {
 pub struct Paste {
     pub id: i32,
     pub key: String,
-    pub content: String,
+    /// Decrypted content bytes; not necessarily UTF-8.
+    pub content: Vec<u8>,
     pub content_type: String,
+    pub mime_type: String,
     pub date_created: DateTime<Utc>,
     pub date_viewed: DateTime<Utc>,
     pub exp_date: Option<DateTime<Utc>>,
+    pub burn_after_reading: bool,
+    /// Successful reads so far, including the one that returned this value.
+    pub view_count: i32,
+    pub max_views: Option<i32>,
+    /// Revision number of `content`; 1 is the original.
+    pub rev: i32,
+    /// Latest revision of the paste.
+    pub current_rev: i32,
+    /// Key of the paste this one was forked from, if it still exists.
+    pub parent_key: Option<String>,
+    /// Named files of a multi-file paste, in upload order; empty otherwise.
+    /// `content` is then the first file's.
+    pub files: Vec<PasteFile>,
+    /// Set for client-encrypted pastes: `content` is the uploader's
+    /// ciphertext and these are its decryption parameters.
+    pub client_params: Option<ClientParams>,
+}
+
+/// One entry in a paste's revision history.
+#[derive(Debug, FromRow, serde::Serialize)]
+pub struct Revision {
+    pub rev: i32,
+    pub content_type: String,
+    pub mime_type: Option<String>,
+    pub date_created: DateTime<Utc>,
 }
 
-/// Returns `true` if an S3 error indicates the object was not found.
+/// What can be said about a paste without reading it, see [`Paste::meta`].
+#[derive(Debug, FromRow, serde::Serialize)]
+pub struct PasteMeta {
+    pub key: String,
+    pub content_type: String,
+    pub mime_type: Option<String>,
+    pub date_created: DateTime<Utc>,
+    pub exp_date: Option<DateTime<Utc>>,
+    pub burn_after_reading: bool,
+    pub max_views: Option<i32>,
+    pub rev: i32,
+    /// Bytes of content, or of all files together.  `None` for revisions
+    /// written before this was recorded.
+    pub size_bytes: Option<i64>,
+    /// Whether reading takes a user-supplied key, server- or client-side.
+    /// `None` until the re-encryption job has recorded the blob's key.
+    pub user_key_required: Option<bool>,
+    /// Header version of the blob, see [`storage::VersionedHeader`].
+    pub blob_version: Option<i32>,
+    /// Whether the storage reconciliation job last found the blob missing
+    /// from the store, see [`crate::reconcile`].
+    pub blob_missing: bool,
+}
+
+/// A new revision of an existing paste, see [`Paste::current_rev`].
+pub struct NewRevision {
+    pub content: Vec<u8>,
+    /// Syntax type; `None` keeps the previous revision's.
+    pub content_type: Option<String>,
+    /// MIME type of `content`, see [`resolve_mime_type`].
+    pub mime_type: String,
+    /// Set when `content` was encrypted by the uploader, as for
+    /// [`NewPaste::client_params`].
+    pub client_params: Option<ClientParams>,
+}
+
+impl NewRevision {
+    /// Publish this as a new revision of the paste at `key`, if
+    /// `edit_token` is its edit token.  Returns the new revision number, or
+    /// `None` when there is no such editable paste or the token doesn't match.
+    ///
+    /// A revision holds a single content, so publishing one over a
+    /// multi-file paste replaces its files.
+    ///
+    /// Like [`NewPaste::insert`], the revision row is only committed once its
+    /// blob has been uploaded.  The paste row is locked for the duration so
+    /// concurrent edits get consecutive revision numbers.
+    pub async fn publish(
+        self,
+        pool: &common::db::DbPool,
+        store: &dyn BlobStore,
+        config: &Config,
+        key: &str,
+        edit_token: &str,
+        user_encryption_key: Option<&str>,
+    ) -> anyhow::Result<Option<i32>> {
+        let mut tx = pool.begin().await?;
+
+        let row: Option<(i32, i32, String, Option<String>)> = sqlx::query_as(&format!(
+            "SELECT id, current_rev, content_type, edit_token_hash FROM pastes
+             WHERE key = $1 AND date_queued IS NULL AND {READABLE}
+                 AND (exp_date IS NULL OR exp_date > $2)
+             FOR UPDATE",
+        ))
+        .bind(key)
+        .bind(Utc::now())
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some((id, current_rev, current_type, Some(hash))) = row else {
+            return Ok(None);
+        };
+        if !common::crypto::hmac_verify(edit_token, &hash, config.signing_key.as_bytes()) {
+            return Ok(None);
+        }
+
+        let content_type = self.content_type.unwrap_or(current_type);
+        let rev = current_rev + 1;
+        let storage_uri = revision_storage_uri(key, rev);
+        let blob = seal_blob_blocking(
+            &self.content,
+            &[],
+            self.client_params.as_ref(),
+            config,
+            user_encryption_key,
+            &blob_aad(id, rev),
+        )
+        .await?;
+        sqlx::query(
+            "INSERT INTO paste_revisions (paste_id, rev, storage_uri, content_type, mime_type, date_created, key_id, blob_bytes, blob_version, content_bytes)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        )
+        .bind(id)
+        .bind(rev)
+        .bind(&storage_uri)
+        .bind(&content_type)
+        .bind(&self.mime_type)
+        .bind(Utc::now())
+        .bind(sealed_key_id(
+            config,
+            user_encryption_key,
+            self.client_params.as_ref(),
+        ))
+        .bind(blob.len() as i64)
+        .bind(sealed_blob_version(
+            user_encryption_key,
+            self.client_params.as_ref(),
+        ))
+        .bind(self.content.len() as i64)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "UPDATE pastes
+             SET storage_uri = $2, current_rev = $3, content_type = $4, mime_type = $5
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(&storage_uri)
+        .bind(rev)
+        .bind(&content_type)
+        .bind(&self.mime_type)
+        .execute(&mut *tx)
+        .await?;
+
+        store.put(&storage_uri, blob).await?;
+        tx.commit().await?;
+
+        info!("Published revision {rev} of paste id={id}");
+        Ok(Some(rev))
+    }
+}
+
+/// Whether `name` is acceptable as a file name in a multi-file paste: a
+/// single non-empty path segment that's safe to put in a URL path.
+pub fn is_valid_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 255
+        && name != "."
+        && name != ".."
+        && !name
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c.is_control())
+}
+
+/// Whether `params` is acceptable for a client-encrypted paste: it names
+/// the algorithm (`alg`) and stays small, since it is stored in every blob
+/// header.  The server doesn't otherwise interpret it.
+pub fn is_valid_client_params(params: &ClientParams) -> bool {
+    params.contains_key("alg")
+        && params.len() <= MAX_CLIENT_PARAMS
+        && params.iter().all(|(k, v)| {
+            !k.is_empty() && k.len() <= MAX_CLIENT_PARAM_LEN && v.len() <= MAX_CLIENT_PARAM_LEN
+        })
+}
+
+/// Pick the MIME type to record for an upload.
 ///
-/// Standard S3 `DeleteObject` returns 204 even for non-existent keys, so this
-/// is mainly defensive for Tigris / non-standard S3 behaviour.
-fn s3_error_is_not_found(e: &anyhow::Error) -> bool {
-    let s = e.to_string();
-    s.contains("NoSuchKey") || s.contains("no such key") || s.contains("404")
+/// `declared` is the request's `Content-Type`.  It is ignored when missing,
+/// when it is `text/plain` (normalised to [`TEXT_MIME_TYPE`]), or when it is
+/// `application/x-www-form-urlencoded` — curl's default for `--data-binary`,
+/// which says nothing about the bytes themselves.  In those cases the type is
+/// inferred from whether `content` is valid UTF-8.
+pub fn resolve_mime_type(declared: Option<&str>, content: &[u8]) -> String {
+    let is_utf8 = std::str::from_utf8(content).is_ok();
+    let fallback = if is_utf8 {
+        TEXT_MIME_TYPE
+    } else {
+        BINARY_MIME_TYPE
+    };
+    let Some(declared) = declared.map(str::trim).filter(|d| !d.is_empty()) else {
+        return fallback.to_string();
+    };
+    let essence = declared
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    let well_formed = essence
+        .split_once('/')
+        .is_some_and(|(t, st)| !t.is_empty() && !st.is_empty());
+    if !well_formed
+        || essence == "text/plain"
+        || essence == "application/x-www-form-urlencoded"
+        || (essence.starts_with("text/") && !is_utf8)
+    {
+        return fallback.to_string();
+    }
+    declared.to_string()
+}
+
+impl Paste {
+    /// The content as a `&str`, or `None` if it isn't valid UTF-8.
+    pub fn content_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.content).ok()
+    }
+
+    /// The file called `name` in a multi-file paste.
+    pub fn file(&self, name: &str) -> Option<&PasteFile> {
+        self.files.iter().find(|f| f.name == name)
+    }
 }
 
-/// A paste that has been identified for deletion and queued to the deletion
-/// worker via the [`Paste::queue_outdated_for_deletion`] channel.
+/// A paste to delete, as claimed from the [`crate::deletion`] queue and handled
+/// by [`Paste::attempt_deletion`].
 pub struct DeletionRequest {
     pub id: i32,
     pub storage_uri: String,
@@ -190,88 +879,122 @@ impl Paste {
         Ok(exists)
     }
 
-    /// Query the DB for expired / stale pastes and enqueue each one on
-    /// `deletion_tx` for the deletion worker to process.
+    /// Delete the paste at `key` immediately if `token` is its deletion
+    /// token.  Returns `false` when there is no such paste or the token
+    /// doesn't match.
+    pub async fn delete_with_token(
+        pool: &common::db::DbPool,
+        store: &dyn BlobStore,
+        config: &Config,
+        key: &str,
+        token: &str,
+    ) -> anyhow::Result<bool> {
+        let row: Option<(i32, String, DateTime<Utc>, Option<String>)> = sqlx::query_as(
+            "SELECT id, storage_uri, date_created, deletion_token_hash FROM pastes WHERE key = $1",
+        )
+        .bind(key)
+        .fetch_optional(pool)
+        .await?;
+        let Some((id, storage_uri, date_created, Some(hash))) = row else {
+            return Ok(false);
+        };
+        if !common::crypto::hmac_verify(token, &hash, config.signing_key.as_bytes()) {
+            return Ok(false);
+        }
+        let req = DeletionRequest {
+            id,
+            storage_uri,
+            date_created,
+        };
+        Paste::attempt_deletion(pool, store, &req).await?;
+        info!("Deleted paste id={id} on owner request");
+        Ok(true)
+    }
+
+    /// Query the DB for expired / stale / burned / viewed-out pastes and
+    /// add a deletion job for each, see [`crate::deletion`].
     ///
     /// The DB rows are **not** deleted here; deletion happens transactionally
     /// in [`Paste::attempt_deletion`] after the corresponding S3 object is
-    /// confirmed removed.  Returns the number of pastes enqueued.
+    /// confirmed removed.  Returns the number of pastes queued.
     ///
-    /// To avoid continuously re-queuing the same paste while the deletion
-    /// worker is catching up, rows whose `date_queued` is less than one hour
-    /// ago are skipped.  `date_queued` is stamped atomically by this query so
-    /// concurrent sweeper instances cannot double-queue the same row.
+    /// Queued rows are stamped with `date_queued` in the same statement that
+    /// adds their jobs, so each paste is queued once however many sweepers
+    /// run; the job table keeps it from then on.
     pub async fn queue_outdated_for_deletion(
         pool: &common::db::DbPool,
-        deletion_tx: &mpsc::Sender<DeletionRequest>,
         max_cutoff: DateTime<Utc>,
         now: DateTime<Utc>,
     ) -> anyhow::Result<u64> {
-        #[derive(FromRow)]
-        struct OutdatedRow {
-            id: i32,
-            storage_uri: String,
-            date_created: DateTime<Utc>,
-        }
-
-        // Atomically stamp date_queued = now() on all qualifying rows and
-        // return them.  Rows that were queued within the last hour are excluded
-        // so the deletion worker has time to process them before they appear
-        // again.
-        let rows: Vec<OutdatedRow> = sqlx::query_as(
-            "UPDATE pastes
-             SET date_queued = $3
-             WHERE id IN (
-                 SELECT id FROM pastes
+        let result = sqlx::query(
+            "WITH doomed AS (
+                 UPDATE pastes
+                 SET date_queued = $3
                  WHERE
-                     ((exp_date IS NOT NULL AND exp_date < $1) OR date_viewed < $2)
-                     AND (date_queued IS NULL OR date_queued < $3 - INTERVAL '1 hour')
+                     ((exp_date IS NOT NULL AND exp_date < $1)
+                      OR date_viewed < $2
+                      OR date_burned IS NOT NULL
+                      OR (max_views IS NOT NULL AND view_count >= max_views))
+                     AND date_queued IS NULL
+                 RETURNING id, storage_uri, date_created
              )
-             RETURNING id, storage_uri, date_created",
+             INSERT INTO paste_deletion_jobs (paste_id, storage_uri, paste_created)
+             SELECT id, storage_uri, date_created FROM doomed
+             ON CONFLICT (paste_id) DO NOTHING",
         )
         .bind(now)
         .bind(max_cutoff)
         .bind(now)
-        .fetch_all(pool)
+        .execute(pool)
         .await?;
-
-        let count = rows.len() as u64;
-
-        for row in rows {
-            let req = DeletionRequest {
-                id: row.id,
-                storage_uri: row.storage_uri,
-                date_created: row.date_created,
-            };
-            if let Err(e) = deletion_tx.try_send(req) {
-                let id = e.into_inner().id;
-                warn!("Deletion queue full, skipping paste id={id}");
-            }
-        }
-
-        Ok(count)
+        Ok(result.rows_affected())
     }
 
-    /// Atomically delete one paste from both the DB and S3.
+    /// Atomically delete one paste from both the DB and the blob store.
     ///
-    /// Opens a DB transaction, deletes the row, then attempts the S3 delete.
+    /// Opens a DB transaction, deletes the row, then attempts the blob delete.
     ///
-    /// | S3 outcome | Transaction |
+    /// | Storage outcome | Transaction |
     /// |---|---|
     /// | Success | Commit |
     /// | Object not found (already gone) | Commit |
     /// | Other error, paste ≥ 30 days old | Commit (bucket TTL cleaned S3) |
-    /// | Other error, paste < 30 days old | Rollback (retry on next sweep) |
+    /// | Other error, paste < 30 days old | Rollback (the job is retried) |
+    ///
+    /// Every revision's blob is deleted; the outcome is that of the first
+    /// failing delete, if any.
     ///
     /// If the DB row is already gone (a previous attempt succeeded), the
     /// function returns `Ok(())` immediately.
     pub async fn attempt_deletion(
         pool: &common::db::DbPool,
-        s3: &aws_sdk_s3::Client,
-        config: &Config,
+        store: &dyn BlobStore,
         req: &DeletionRequest,
     ) -> anyhow::Result<()> {
-        let mut tx = pool.begin().await?;
+        let mut conn = pool.acquire().await?;
+        Self::attempt_deletion_on(&mut conn, store, req).await
+    }
+
+    /// [`Self::attempt_deletion`] on an existing connection.  Called inside
+    /// a transaction, the deletion runs in a savepoint and only takes effect
+    /// when the caller commits.
+    pub async fn attempt_deletion_on(
+        conn: &mut sqlx::PgConnection,
+        store: &dyn BlobStore,
+        req: &DeletionRequest,
+    ) -> anyhow::Result<()> {
+        let mut tx = conn.begin().await?;
+
+        // `req.storage_uri` may predate an edit, so take every revision's
+        // blob from the DB before the cascade removes them.
+        let mut uris: Vec<String> =
+            sqlx::query_scalar("SELECT storage_uri FROM paste_revisions WHERE paste_id = $1")
+                .bind(req.id)
+                .fetch_all(&mut *tx)
+                .await?;
+        if !uris.contains(&req.storage_uri) {
+            uris.push(req.storage_uri.clone());
+        }
 
         let rows_affected = sqlx::query("DELETE FROM pastes WHERE id = $1")
             .bind(req.id)
@@ -285,15 +1008,18 @@ impl Paste {
             return Ok(());
         }
 
-        // Attempt S3 deletion.
-        match storage::delete_object(s3, &config.s3_bucket, &req.storage_uri).await {
-            Ok(_) => {
-                tx.commit().await?;
-                Ok(())
+        // Attempt blob deletion.
+        let mut outcome = Ok(());
+        for uri in &uris {
+            if let Err(e) = store.delete(uri).await {
+                outcome = Err(e);
+                break;
             }
-            Err(ref e) if s3_error_is_not_found(e) => {
-                // Object already gone — safe to commit the DB deletion.
+        }
+        match outcome {
+            Ok(()) => {
                 tx.commit().await?;
+                METRICS.deletions.with_label_values(&["committed"]).inc();
                 Ok(())
             }
             Err(e) => {
@@ -307,14 +1033,16 @@ impl Paste {
                         req.id
                     );
                     tx.commit().await?;
+                    METRICS.deletions.with_label_values(&["committed"]).inc();
                     Ok(())
                 } else {
-                    // Transient S3 error — roll back so the sweeper retries.
+                    // Transient S3 error — roll back so the job is retried.
                     warn!(
                         "S3 deletion failed for paste id={}, rolling back for retry: {e}",
                         req.id
                     );
                     tx.rollback().await?;
+                    METRICS.deletions.with_label_values(&["rolled_back"]).inc();
                     Err(anyhow::anyhow!(
                         "S3 deletion failed for paste id={}: {e}",
                         req.id
@@ -324,26 +1052,38 @@ impl Paste {
         }
     }
 
-    /// Update `date_viewed`, fetch from S3, decrypt, verify signature, and return.
+    /// Update `date_viewed`, fetch the blob, decrypt, verify signature, and return.
     ///
     /// `user_enc_key` is the user-supplied password, required only when the
     /// paste was stored with user-key encryption (i.e. the blob header has a salt).
+    /// Without it the read fails with [`PasteError::KeyRequired`], and with
+    /// the wrong one with [`PasteError::WrongKey`]; neither counts as a view.
+    /// Wrong keys are counted against the paste and `client`, and too many
+    /// of them refuse further attempts, see [`crate::keyguard`].
+    ///
+    /// Burn-after-reading pastes are claimed and deleted by the first
+    /// successful read; every later read reports the paste as not found.
+    ///
+    /// Every successful read increments `view_count`.  When that reaches
+    /// `max_views` the paste is queued for deletion and later reads report it
+    /// as not found.
     pub async fn touch_and_get(
         pool: &common::db::DbPool,
-        s3: &aws_sdk_s3::Client,
+        store: &dyn BlobStore,
         config: &Config,
         key: &str,
         user_enc_key: Option<&str>,
-    ) -> anyhow::Result<Self> {
-        let row = sqlx::query_as::<_, PasteRow>(
-            "UPDATE pastes SET date_viewed = $1 WHERE key = $2
-             RETURNING id, key, storage_uri, content_type, date_created, date_viewed, exp_date, date_queued",
-        )
+        client: Option<&str>,
+    ) -> Result<Self, PasteError> {
+        let row = sqlx::query_as::<_, PasteRow>(&format!(
+            "UPDATE pastes SET date_viewed = $1 WHERE key = $2 AND {READABLE}
+             RETURNING {PASTE_ROW_COLUMNS}",
+        ))
         .bind(Utc::now())
         .bind(key)
         .fetch_optional(pool)
         .await?
-        .ok_or_else(|| anyhow::anyhow!("paste not found"))?;
+        .ok_or(PasteError::NotFound)?;
 
         // Expiry check — route through attempt_deletion for transactional cleanup.
         if let Some(exp_date) = row.exp_date {
@@ -353,47 +1093,376 @@ impl Paste {
                     storage_uri: row.storage_uri.clone(),
                     date_created: row.date_created,
                 };
-                if let Err(e) = Paste::attempt_deletion(pool, s3, config, &req).await {
+                if let Err(e) = Paste::attempt_deletion(pool, store, &req).await {
                     warn!("Failed to clean up expired paste id={}: {e}", req.id);
                 }
-                return Err(anyhow::anyhow!("paste expired"));
+                return Err(PasteError::Expired);
             }
         }
 
-        // Fetch blob from S3 and parse header.
-        let blob = storage::get_object(s3, &config.s3_bucket, &row.storage_uri).await?;
-        let (header, ciphertext) = storage::decode_blob(&blob)?;
-
-        // AAD must match what was used during encryption.
-        let aad = row.id.to_be_bytes();
-
-        // Decrypt — the header version drives the decryption logic.
-        let plaintext_bytes = header.decrypt(
-            &ciphertext,
-            user_enc_key.map(|k| k.as_bytes()),
-            &[config.encryption_key.as_key_ref()],
-            &aad,
-        )?;
+        // Fetch, decrypt and verify the latest revision.  AAD must match
+        // what was used during encryption.
+        let opened = open_guarded(
+            pool,
+            store,
+            config,
+            &row,
+            &row.storage_uri,
+            row.current_rev,
+            user_enc_key,
+            client,
+        )
+        .await?;
 
-        let content =
-            String::from_utf8(plaintext_bytes).map_err(|e| anyhow::anyhow!("utf8: {e}"))?;
+        // Count the view only after a successful decrypt, so wrong passwords
+        // don't use up views.  The conditional update makes the limit hold
+        // under concurrent reads; the read that hits the limit stamps
+        // `date_queued` and adds the deletion job in the same statement.
+        let view_count: i32 = sqlx::query_scalar(
+            "WITH counted AS (
+                 UPDATE pastes
+                 SET view_count = view_count + 1,
+                     date_queued = CASE WHEN view_count + 1 >= max_views THEN $2 ELSE date_queued END
+                 WHERE id = $1 AND (max_views IS NULL OR view_count < max_views)
+                 RETURNING id, storage_uri, date_created, view_count, max_views
+             ), queued AS (
+                 INSERT INTO paste_deletion_jobs (paste_id, storage_uri, paste_created)
+                 SELECT id, storage_uri, date_created FROM counted WHERE view_count >= max_views
+                 ON CONFLICT (paste_id) DO NOTHING
+             )
+             SELECT view_count FROM counted",
+        )
+        .bind(row.id)
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await?
+        .ok_or(PasteError::NotFound)?;
 
-        // Verify HMAC signature from the blob header.
-        if !common::crypto::hmac_verify(&content, header.sig(), config.signing_key.as_bytes()) {
-            error!("HMAC verification failed for paste key={key}");
-            return Err(anyhow::anyhow!("decryption failure"));
+        // Burn-after-reading: only the reader that stamps `date_burned` gets
+        // the content.  Claiming after decryption means a wrong password
+        // doesn't burn the paste.
+        if row.burn_after_reading {
+            let claimed = sqlx::query(
+                "UPDATE pastes SET date_burned = $1 WHERE id = $2 AND date_burned IS NULL",
+            )
+            .bind(Utc::now())
+            .bind(row.id)
+            .execute(pool)
+            .await?
+            .rows_affected();
+            if claimed == 0 {
+                return Err(PasteError::NotFound);
+            }
+            let req = DeletionRequest {
+                id: row.id,
+                storage_uri: row.storage_uri.clone(),
+                date_created: row.date_created,
+            };
+            // On failure the sweeper picks the burned row up later.
+            if let Err(e) = Paste::attempt_deletion(pool, store, &req).await {
+                warn!("Failed to delete burned paste id={}: {e}", req.id);
+            }
         }
 
+        METRICS.pastes_read.inc();
         Ok(Paste {
             id: row.id,
             key: row.key,
-            content,
+            content: opened.content,
             content_type: row.content_type,
+            mime_type: row.mime_type.unwrap_or_else(|| TEXT_MIME_TYPE.to_string()),
+            date_created: row.date_created,
+            date_viewed: row.date_viewed,
+            exp_date: row.exp_date,
+            burn_after_reading: row.burn_after_reading,
+            view_count,
+            max_views: row.max_views,
+            rev: row.current_rev,
+            current_rev: row.current_rev,
+            parent_key: row.parent_key,
+            files: opened.files,
+            client_params: opened.client_params,
+        })
+    }
+
+    /// Revision `rev` of the paste at `key`.
+    ///
+    /// Unlike [`Paste::touch_and_get`] this doesn't count as a view, so it
+    /// refuses burn-after-reading and `max_views` pastes.  Keys are checked
+    /// as they are there.
+    pub async fn get_revision(
+        pool: &common::db::DbPool,
+        store: &dyn BlobStore,
+        config: &Config,
+        key: &str,
+        rev: i32,
+        user_enc_key: Option<&str>,
+        client: Option<&str>,
+    ) -> Result<Self, PasteError> {
+        let row = sqlx::query_as::<_, PasteRow>(&format!(
+            "SELECT {PASTE_ROW_COLUMNS} FROM pastes
+             WHERE key = $1 AND {READABLE} AND NOT burn_after_reading AND max_views IS NULL
+                 AND (exp_date IS NULL OR exp_date > $2)",
+        ))
+        .bind(key)
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await?
+        .ok_or(PasteError::NotFound)?;
+
+        let (storage_uri, content_type, mime_type): (String, String, Option<String>) =
+            sqlx::query_as(
+                "SELECT storage_uri, content_type, mime_type FROM paste_revisions
+                 WHERE paste_id = $1 AND rev = $2",
+            )
+            .bind(row.id)
+            .bind(rev)
+            .fetch_optional(pool)
+            .await?
+            .ok_or(PasteError::NotFound)?;
+
+        let opened = open_guarded(
+            pool,
+            store,
+            config,
+            &row,
+            &storage_uri,
+            rev,
+            user_enc_key,
+            client,
+        )
+        .await?;
+
+        Ok(Paste {
+            id: row.id,
+            key: row.key,
+            content: opened.content,
+            content_type,
+            mime_type: mime_type.unwrap_or_else(|| TEXT_MIME_TYPE.to_string()),
             date_created: row.date_created,
             date_viewed: row.date_viewed,
             exp_date: row.exp_date,
+            burn_after_reading: row.burn_after_reading,
+            view_count: row.view_count,
+            max_views: row.max_views,
+            rev,
+            current_rev: row.current_rev,
+            parent_key: row.parent_key,
+            files: opened.files,
+            client_params: opened.client_params,
         })
     }
+
+    /// Revision history of the paste at `key`, oldest first, without reading
+    /// any blobs.  `None` under the same conditions that make
+    /// [`Paste::get_revision`] report the paste as not found.
+    pub async fn history(
+        pool: &common::db::DbPool,
+        key: &str,
+    ) -> anyhow::Result<Option<Vec<Revision>>> {
+        let id: Option<i32> = sqlx::query_scalar(&format!(
+            "SELECT id FROM pastes
+             WHERE key = $1 AND {READABLE} AND NOT burn_after_reading AND max_views IS NULL
+                 AND (exp_date IS NULL OR exp_date > $2)",
+        ))
+        .bind(key)
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        let revisions = sqlx::query_as::<_, Revision>(
+            "SELECT rev, content_type, mime_type, date_created FROM paste_revisions
+             WHERE paste_id = $1 ORDER BY rev",
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+        Ok(Some(revisions))
+    }
+
+    /// Whether reading the paste at `key` uses something up — it is
+    /// burn-after-reading or has a `max_views` limit — checked without
+    /// touching or decrypting it.  `None` if there is no such readable paste.
+    pub async fn is_read_limited(
+        pool: &common::db::DbPool,
+        key: &str,
+    ) -> anyhow::Result<Option<bool>> {
+        let limited: Option<bool> = sqlx::query_scalar(&format!(
+            "SELECT burn_after_reading OR max_views IS NOT NULL FROM pastes
+             WHERE key = $1 AND {READABLE}",
+        ))
+        .bind(key)
+        .fetch_optional(pool)
+        .await?;
+        Ok(limited)
+    }
+
+    /// Metadata of the paste at `key`, from the database alone.  Unlike
+    /// [`Paste::touch_and_get`] this neither decrypts nor counts as a view,
+    /// so it doesn't keep the paste alive or use up a burn-after-reading or
+    /// `max_views` paste.  `None` if there is no such readable, unexpired
+    /// paste.
+    pub async fn meta(pool: &common::db::DbPool, key: &str) -> anyhow::Result<Option<PasteMeta>> {
+        let meta = sqlx::query_as(&format!(
+            "SELECT p.key, p.content_type, p.mime_type, p.date_created, p.exp_date,
+                 p.burn_after_reading, p.max_views, p.current_rev AS rev,
+                 r.content_bytes AS size_bytes, r.key_id = $2 AS user_key_required,
+                 r.blob_version, r.date_blob_missing IS NOT NULL AS blob_missing
+             FROM pastes p JOIN paste_revisions r ON r.paste_id = p.id AND r.rev = p.current_rev
+             WHERE p.key = $1 AND {READABLE} AND (p.exp_date IS NULL OR p.exp_date > $3)",
+        ))
+        .bind(key)
+        .bind(USER_KEY_ID)
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await?;
+        Ok(meta)
+    }
+
+    /// Re-encrypt up to `limit` revision blobs that aren't known to be on
+    /// the primary key, starting after the `(paste_id, rev)` cursor `after`.
+    ///
+    /// Blobs on a retired key are decrypted with the key ring and re-sealed
+    /// under the primary key at the same storage URI; blobs whose key or
+    /// header version wasn't recorded yet (rows predating `key_id` or
+    /// `blob_version`) just get them recorded.  Progress
+    /// lives in `paste_revisions.key_id`, so the job is idempotent and picks
+    /// up where it left off after a restart.  Rows locked by another worker
+    /// are skipped and retried on the next pass.  Rows whose blob the storage
+    /// reconciliation job flagged as missing are left alone, and a blob that
+    /// fails to re-encrypt is stamped in `date_reencrypt_failed` and not
+    /// tried again for [`REENCRYPT_RETRY_HOURS`].  Returns the cursor to
+    /// continue from, `None` once the pass is done.
+    pub async fn reencrypt_batch(
+        pool: &common::db::DbPool,
+        store: &dyn BlobStore,
+        config: &Config,
+        after: (i32, i32),
+        limit: i64,
+    ) -> anyhow::Result<Option<(i32, i32)>> {
+        let primary = config.encryption_key.id.as_str();
+        let batch: Vec<(i32, i32)> = sqlx::query_as(
+            "SELECT paste_id, rev FROM paste_revisions
+             WHERE (paste_id, rev) > ($1, $2)
+                 AND (key_id IS NULL OR key_id NOT IN ($3, $4) OR blob_version IS NULL)
+                 AND date_blob_missing IS NULL
+                 AND (date_reencrypt_failed IS NULL OR date_reencrypt_failed < $5)
+             ORDER BY paste_id, rev
+             LIMIT $6",
+        )
+        .bind(after.0)
+        .bind(after.1)
+        .bind(primary)
+        .bind(USER_KEY_ID)
+        .bind(Utc::now() - Duration::hours(REENCRYPT_RETRY_HOURS))
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        for &(paste_id, rev) in &batch {
+            if let Err(e) = Self::reencrypt_revision(pool, store, config, paste_id, rev).await {
+                warn!("Error re-encrypting paste id={paste_id} rev={rev}, retrying in {REENCRYPT_RETRY_HOURS}h: {e}");
+                sqlx::query(
+                    "UPDATE paste_revisions SET date_reencrypt_failed = $3
+                     WHERE paste_id = $1 AND rev = $2",
+                )
+                .bind(paste_id)
+                .bind(rev)
+                .bind(Utc::now())
+                .execute(pool)
+                .await?;
+            }
+        }
+        Ok(batch.last().copied())
+    }
+
+    async fn reencrypt_revision(
+        pool: &common::db::DbPool,
+        store: &dyn BlobStore,
+        config: &Config,
+        paste_id: i32,
+        rev: i32,
+    ) -> anyhow::Result<()> {
+        let mut tx = pool.begin().await?;
+        // Holding the row lock keeps a concurrent deletion from removing the
+        // blob underneath us.
+        let storage_uri: Option<String> = sqlx::query_scalar(
+            "SELECT storage_uri FROM paste_revisions
+             WHERE paste_id = $1 AND rev = $2
+             FOR UPDATE SKIP LOCKED",
+        )
+        .bind(paste_id)
+        .bind(rev)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(storage_uri) = storage_uri else {
+            return Ok(());
+        };
+
+        let blob = store.get(&storage_uri).await?;
+        let (header, _) = storage::decode_blob(&blob)?;
+        let (key_id, blob_bytes, blob_version) = match header.server_key_id() {
+            None => (USER_KEY_ID, blob.len(), header.version()),
+            Some(id) if id == config.encryption_key.id => (id, blob.len(), header.version()),
+            Some(id) => {
+                let aad = blob_aad(paste_id, rev);
+                let opened = decrypt_blob(&blob, config, &storage_uri, &aad, None)?;
+                let sealed = seal_blob(&opened.content, &opened.files, None, config, None, &aad)?;
+                let sealed_bytes = sealed.len();
+                store.put(&storage_uri, sealed).await?;
+                info!("Re-encrypted paste id={paste_id} rev={rev} from key {id:?}");
+                (
+                    config.encryption_key.id.as_str(),
+                    sealed_bytes,
+                    BlobHeaderV2::VERSION,
+                )
+            }
+        };
+
+        sqlx::query(
+            "UPDATE paste_revisions
+             SET key_id = $3, blob_bytes = $4, blob_version = $5, date_reencrypt_failed = NULL
+             WHERE paste_id = $1 AND rev = $2",
+        )
+        .bind(paste_id)
+        .bind(rev)
+        .bind(key_id)
+        .bind(blob_bytes as i64)
+        .bind(blob_version as i32)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Total bytes of stored revision blobs, how many revisions' blob sizes
+    /// weren't recorded (written before sizes were), and how many revisions
+    /// are flagged as missing their blob.
+    pub async fn storage_usage(pool: &common::db::DbPool) -> anyhow::Result<(i64, i64, i64)> {
+        let usage = sqlx::query_as(
+            "SELECT COALESCE(SUM(blob_bytes), 0)::int8, COUNT(*) FILTER (WHERE blob_bytes IS NULL),
+                 COUNT(*) FILTER (WHERE date_blob_missing IS NOT NULL)
+             FROM paste_revisions",
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(usage)
+    }
+
+    /// Number of revision blobs per recorded key id, where `None` means not
+    /// yet recorded and [`USER_KEY_ID`] marks user-key blobs.
+    pub async fn key_usage(
+        pool: &common::db::DbPool,
+    ) -> anyhow::Result<Vec<(Option<String>, i64)>> {
+        let usage = sqlx::query_as(
+            "SELECT key_id, COUNT(*) FROM paste_revisions GROUP BY key_id ORDER BY key_id",
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(usage)
+    }
 }
 
 pub static CONTENT_TYPES: [&str; 147] = [
@@ -552,6 +1621,32 @@ mod tests {
 
     const AMBIGUOUS: &[char] = &['l', '1', 'i', 'o', '0'];
 
+    #[test]
+    fn client_params_validation() {
+        let params = |pairs: &[(&str, &str)]| -> ClientParams {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert!(is_valid_client_params(&params(&[
+            ("alg", "AES-GCM"),
+            ("iv", "abc")
+        ])));
+        assert!(!is_valid_client_params(&params(&[("iv", "abc")])));
+        assert!(!is_valid_client_params(&params(&[
+            ("alg", "AES-GCM"),
+            ("", "x")
+        ])));
+        let long = "x".repeat(MAX_CLIENT_PARAM_LEN + 1);
+        assert!(!is_valid_client_params(&params(&[("alg", &long)])));
+        let many: ClientParams = (0..=MAX_CLIENT_PARAMS)
+            .map(|i| (format!("k{i}"), String::new()))
+            .chain([("alg".to_string(), "AES-GCM".to_string())])
+            .collect();
+        assert!(!is_valid_client_params(&many));
+    }
+
     #[test]
     fn test_gen_key_contains_no_ambiguous_chars() {
         for _ in 0..200 {
@@ -720,6 +1815,64 @@ mod tests {
         assert_eq!(dec, plaintext);
     }
 
+    #[test]
+    fn test_resolve_mime_type_infers_text_and_binary() {
+        assert_eq!(resolve_mime_type(None, b"hello"), TEXT_MIME_TYPE);
+        assert_eq!(resolve_mime_type(None, &[0xff, 0x00]), BINARY_MIME_TYPE);
+    }
+
+    #[test]
+    fn test_resolve_mime_type_ignores_curl_form_default() {
+        let form = Some("application/x-www-form-urlencoded");
+        assert_eq!(resolve_mime_type(form, b"a=b"), TEXT_MIME_TYPE);
+        assert_eq!(resolve_mime_type(form, &[0x89, b'P']), BINARY_MIME_TYPE);
+    }
+
+    #[test]
+    fn test_resolve_mime_type_keeps_declared_type() {
+        assert_eq!(
+            resolve_mime_type(Some("image/png"), &[0x89, b'P', b'N', b'G']),
+            "image/png"
+        );
+        assert_eq!(
+            resolve_mime_type(Some("text/plain"), b"plain"),
+            TEXT_MIME_TYPE
+        );
+        assert_eq!(
+            resolve_mime_type(Some("text/csv"), &[0xff]),
+            BINARY_MIME_TYPE
+        );
+        assert_eq!(resolve_mime_type(Some("garbage"), b"x"), TEXT_MIME_TYPE);
+    }
+
+    #[test]
+    fn test_revision_one_keeps_legacy_layout() {
+        assert_eq!(revision_storage_uri("abcde", 1), "abcde");
+        assert_eq!(blob_aad(42, 1), 42i32.to_be_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_later_revisions_get_distinct_uri_and_aad() {
+        assert_eq!(revision_storage_uri("abcde", 2), "abcde-r2");
+        assert_ne!(blob_aad(42, 2), blob_aad(42, 3));
+        assert_ne!(blob_aad(42, 2), blob_aad(42, 1));
+    }
+
+    #[test]
+    fn test_is_valid_file_name() {
+        for ok in [
+            "Cargo.toml",
+            "main.rs",
+            ".gitignore",
+            "notes with spaces.txt",
+        ] {
+            assert!(is_valid_file_name(ok), "{ok:?}");
+        }
+        for bad in ["", ".", "..", "src/main.rs", "a\\b", "tab\there"] {
+            assert!(!is_valid_file_name(bad), "{bad:?}");
+        }
+    }
+
     #[test]
     fn test_content_types_includes_text() {
         assert!(
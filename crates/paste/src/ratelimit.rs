@@ -0,0 +1,256 @@
+//! Per-client token-bucket rate limits on paste creation and edits,
+//! and on wrong keys, see [`crate::keyguard`].
+//!
+//! Buckets live in the `paste_rate_limits` table so every instance draws
+//! from the same ones.  Clients are identified by the `fly-client-ip`
+//! header set by the Fly proxy; requests without it (local development,
+//! tests) aren't limited.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::time::Duration;
+use tracing::{error, warn};
+
+use crate::error::{LegacyError, PasteError};
+use crate::State as AppState;
+
+/// Header carrying the client address, as read by `mono`'s trace middleware.
+const CLIENT_IP_HEADER: &str = "fly-client-ip";
+
+/// The client address from `headers`, if the proxy set it.
+pub fn client_ip(headers: &HeaderMap) -> Option<&str> {
+    headers.get(CLIENT_IP_HEADER).and_then(|h| h.to_str().ok())
+}
+
+/// Bucket sizes and refill rates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limit {
+    /// Most tokens the bucket holds, i.e. the allowed burst.
+    pub capacity: f64,
+    /// Tokens added per second.
+    pub per_second: f64,
+}
+
+impl Limit {
+    pub fn per_minute(n: u64) -> Self {
+        Self {
+            capacity: n as f64,
+            per_second: n as f64 / 60.0,
+        }
+    }
+
+    pub fn per_hour(n: u64) -> Self {
+        Self {
+            capacity: n as f64,
+            per_second: n as f64 / 3600.0,
+        }
+    }
+
+    /// Refill a bucket that held `tokens` `elapsed` seconds ago and try to
+    /// take `cost` from it.  Returns the new level and, if there weren't
+    /// enough tokens, how long until there will be; nothing is taken then.
+    /// A `cost` over the capacity is charged as a full bucket.
+    fn take(&self, tokens: f64, elapsed: f64, cost: f64) -> (f64, Option<Duration>) {
+        let level = (tokens + elapsed.max(0.0) * self.per_second).min(self.capacity);
+        let cost = cost.min(self.capacity);
+        if level >= cost {
+            (level - cost, None)
+        } else {
+            let wait = (cost - level) / self.per_second;
+            (level, Some(Duration::from_secs_f64(wait.ceil())))
+        }
+    }
+}
+
+/// Take `cost` tokens from `client`'s `bucket`.  `Ok(None)` if the request
+/// may go ahead, otherwise how long the client should wait.
+pub async fn take(
+    pool: &common::db::DbPool,
+    client: &str,
+    bucket: &str,
+    limit: Limit,
+    cost: f64,
+) -> anyhow::Result<Option<Duration>> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO paste_rate_limits (client, bucket, tokens) VALUES ($1, $2, $3)
+         ON CONFLICT (client, bucket) DO NOTHING",
+    )
+    .bind(client)
+    .bind(bucket)
+    .bind(limit.capacity)
+    .execute(&mut *tx)
+    .await?;
+    // clock_timestamp(), not now(): a transaction that waited on the row
+    // lock must not see time running backwards.
+    let (tokens, elapsed): (f64, f64) = sqlx::query_as(
+        "SELECT tokens, EXTRACT(EPOCH FROM clock_timestamp() - updated_at)::float8
+         FROM paste_rate_limits WHERE client = $1 AND bucket = $2 FOR UPDATE",
+    )
+    .bind(client)
+    .bind(bucket)
+    .fetch_one(&mut *tx)
+    .await?;
+    let (tokens, wait) = limit.take(tokens, elapsed, cost);
+    sqlx::query(
+        "UPDATE paste_rate_limits SET tokens = $3, updated_at = clock_timestamp()
+         WHERE client = $1 AND bucket = $2",
+    )
+    .bind(client)
+    .bind(bucket)
+    .bind(tokens)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(wait)
+}
+
+/// Put `tokens` back in `client`'s `bucket`, for a request [`take`] charged
+/// that turned out not to count against it.
+pub async fn refund(
+    pool: &common::db::DbPool,
+    client: &str,
+    bucket: &str,
+    limit: Limit,
+    tokens: f64,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "UPDATE paste_rate_limits SET tokens = LEAST(tokens + $3, $4)
+         WHERE client = $1 AND bucket = $2",
+    )
+    .bind(client)
+    .bind(bucket)
+    .bind(tokens)
+    .bind(limit.capacity)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Drop buckets untouched for longer than `idle`.  A bucket that has had
+/// time to refill completely is the same as a missing one.
+pub async fn prune(pool: &common::db::DbPool, idle: Duration) -> anyhow::Result<u64> {
+    let result = sqlx::query(
+        "DELETE FROM paste_rate_limits
+         WHERE updated_at < clock_timestamp() - make_interval(secs => $1)",
+    )
+    .bind(idle.as_secs_f64())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Middleware for the paste-creation and edit routes: one token per request
+/// from the `requests` bucket and one per body byte from the `bytes` bucket,
+/// per [`crate::Config`]; a refused request is charged to neither.  Errors talking to the database let the request
+/// through rather than take creation down with it.
+pub async fn limit_creation(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let Some(client) = client_ip(req.headers()).map(str::to_string) else {
+        return next.run(req).await;
+    };
+    let config = &state.config;
+    // The versioned API answers in its own error format.
+    let versioned = req.uri().path().starts_with("/api/v1/");
+
+    // Without a Content-Length the body has to be read to be charged for.
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.parse::<u64>().ok());
+    let (req, bytes) = match content_length {
+        Some(len) => (req, len),
+        None if config.rate_limit_bytes_per_hour > 0 => {
+            let (parts, body) = req.into_parts();
+            // Room for JSON and base64 overhead; the handler enforces the
+            // real limit.
+            let limit = config.max_paste_bytes.saturating_mul(2);
+            let Ok(body) = axum::body::to_bytes(body, limit).await else {
+                return refuse(PasteError::TooLarge, versioned);
+            };
+            let len = body.len() as u64;
+            (Request::from_parts(parts, body.into()), len)
+        }
+        None => (req, 0),
+    };
+
+    let mut checks = Vec::with_capacity(2);
+    if config.rate_limit_requests_per_minute > 0 {
+        checks.push((
+            "requests",
+            Limit::per_minute(config.rate_limit_requests_per_minute),
+            1.0,
+        ));
+    }
+    if config.rate_limit_bytes_per_hour > 0 {
+        checks.push((
+            "bytes",
+            Limit::per_hour(config.rate_limit_bytes_per_hour),
+            bytes as f64,
+        ));
+    }
+
+    let mut charged = Vec::with_capacity(checks.len());
+    for (bucket, limit, cost) in checks {
+        match take(&state.db, &client, bucket, limit, cost).await {
+            Ok(None) => charged.push((bucket, limit, cost)),
+            Ok(Some(wait)) => {
+                warn!("Rate limited paste creation from {client} ({bucket})");
+                // A refused request costs nothing from the other buckets.
+                for (bucket, limit, cost) in charged {
+                    if let Err(e) = refund(&state.db, &client, bucket, limit, cost).await {
+                        error!("Error refunding {bucket} rate limit for {client}: {e}");
+                    }
+                }
+                return refuse(PasteError::RateLimited(wait), versioned);
+            }
+            Err(e) => {
+                error!("Error checking paste rate limit for {client}: {e}");
+                break;
+            }
+        }
+    }
+    next.run(req).await
+}
+
+/// `e` in the format of the route that was refused.
+fn refuse(e: PasteError, versioned: bool) -> Response {
+    if versioned {
+        e.into_response()
+    } else {
+        LegacyError::from(e).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limit = Limit::per_minute(60);
+        assert_eq!(limit.take(60.0, 0.0, 1.0), (59.0, None));
+        assert_eq!(limit.take(0.5, 0.0, 1.0).1, Some(Duration::from_secs(1)));
+        assert_eq!(limit.take(0.0, 10.0, 1.0), (9.0, None));
+        assert_eq!(limit.take(50.0, 3600.0, 1.0), (59.0, None));
+    }
+
+    #[test]
+    fn denied_takes_nothing() {
+        let limit = Limit::per_hour(3600);
+        let (level, wait) = limit.take(100.0, 0.0, 500.0);
+        assert_eq!(level, 100.0);
+        assert_eq!(wait, Some(Duration::from_secs(400)));
+    }
+
+    #[test]
+    fn oversized_cost_needs_a_full_bucket() {
+        let limit = Limit::per_hour(1000);
+        assert_eq!(limit.take(1000.0, 0.0, 5000.0), (0.0, None));
+        assert!(limit.take(999.0, 0.0, 5000.0).1.is_some());
+    }
+}
@@ -0,0 +1,143 @@
+//! Storage reconciliation.
+//!
+//! The blob store and the database can drift apart.
+//! [`NewPaste::insert`](crate::models::NewPaste::insert) uploads before it
+//! commits, so a failed commit leaves an object without a row, and
+//! [`Paste::attempt_deletion`](crate::models::Paste::attempt_deletion)
+//! commits without deleting the object once a paste is past the bucket TTL.
+//! [`reconcile`] lists the whole store against `paste_revisions`, deleting
+//! objects no revision refers to and flagging revisions whose object is gone
+//! in `paste_revisions.date_blob_missing`.
+//!
+//! Uploads land before their rows commit, so anything younger than the grace
+//! period is left alone on both sides.
+//!
+//! The flag shows in paste metadata and the `paste_blobs_missing` gauge, and
+//! keeps the re-encryption job off those rows.  A dry run's [`Report`] is
+//! served at `/reconcile` on the internal listener.
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::FromRow;
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::storage::BlobStore;
+
+/// A revision whose blob isn't in the store.
+#[derive(Debug, FromRow, Serialize)]
+pub struct MissingBlob {
+    pub key: String,
+    pub rev: i32,
+    pub storage_uri: String,
+}
+
+/// What [`reconcile`] found, and in a dry run would have done.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub dry_run: bool,
+    /// Objects listed.
+    pub objects: usize,
+    /// Unreferenced objects older than the grace period, deleted unless
+    /// this is a dry run.
+    pub orphans: Vec<String>,
+    /// Unreferenced objects within the grace period, left alone.
+    pub recent_orphans: usize,
+    /// Orphans that failed to delete; the next run tries again.
+    pub delete_failures: usize,
+    /// Revisions older than the grace period whose blob wasn't listed.
+    pub missing: Vec<MissingBlob>,
+}
+
+/// Diff the blob store against `paste_revisions`.  Unless `dry_run`,
+/// orphaned objects older than `grace` are deleted and the missing-blob
+/// flags are brought up to date.
+pub async fn reconcile(
+    pool: &common::db::DbPool,
+    store: &dyn BlobStore,
+    grace: Duration,
+    dry_run: bool,
+) -> anyhow::Result<Report> {
+    let cutoff = Utc::now() - chrono::Duration::from_std(grace)?;
+    let mut report = Report {
+        dry_run,
+        ..Report::default()
+    };
+
+    let mut listed = HashSet::new();
+    let mut continuation = None;
+    loop {
+        let page = store.list(continuation).await?;
+        report.objects += page.objects.len();
+        let keys: Vec<&str> = page.objects.iter().map(|o| o.key.as_str()).collect();
+        let referenced: HashSet<String> = sqlx::query_scalar(
+            "SELECT DISTINCT storage_uri FROM paste_revisions WHERE storage_uri = ANY($1)",
+        )
+        .bind(&keys)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        for object in page.objects {
+            if !referenced.contains(&object.key) {
+                if object.last_modified >= cutoff {
+                    report.recent_orphans += 1;
+                } else if dry_run {
+                    report.orphans.push(object.key.clone());
+                } else {
+                    match store.delete(&object.key).await {
+                        Ok(()) => {
+                            info!("Deleted orphaned blob {:?}", object.key);
+                            report.orphans.push(object.key.clone());
+                        }
+                        Err(e) => {
+                            warn!("Failed to delete orphaned blob {:?}: {e}", object.key);
+                            report.delete_failures += 1;
+                        }
+                    }
+                }
+            }
+            listed.insert(object.key);
+        }
+
+        match page.next {
+            Some(next) => continuation = Some(next),
+            None => break,
+        }
+    }
+
+    let revisions: Vec<MissingBlob> = sqlx::query_as(
+        "SELECT p.key, r.rev, r.storage_uri
+         FROM paste_revisions r JOIN pastes p ON p.id = r.paste_id
+         WHERE r.date_created < $1
+         ORDER BY p.key, r.rev",
+    )
+    .bind(cutoff)
+    .fetch_all(pool)
+    .await?;
+    report.missing = revisions
+        .into_iter()
+        .filter(|r| !listed.contains(&r.storage_uri))
+        .collect();
+
+    if !dry_run {
+        let missing: Vec<&str> = report
+            .missing
+            .iter()
+            .map(|m| m.storage_uri.as_str())
+            .collect();
+        sqlx::query(
+            "UPDATE paste_revisions
+             SET date_blob_missing = CASE
+                 WHEN storage_uri = ANY($1) THEN COALESCE(date_blob_missing, NOW())
+             END
+             WHERE date_blob_missing IS NOT NULL OR storage_uri = ANY($1)",
+        )
+        .bind(&missing)
+        .execute(pool)
+        .await?;
+    }
+    Ok(report)
+}
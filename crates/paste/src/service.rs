@@ -1,6 +1,6 @@
 use axum::{
     http::{header, Method},
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
 use std::sync::Arc;
@@ -10,39 +10,110 @@ use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::{ServeDir, ServeFile};
 use tracing::{debug, error, info, warn};
 
+use crate::api;
+use crate::deletion;
 use crate::handlers;
+use crate::metrics::METRICS;
 use crate::models;
+use crate::ratelimit;
+use crate::reconcile;
 use crate::Resources;
 use crate::State;
 
-/// Capacity of the paste-deletion channel.  If the worker falls behind and
-/// the channel fills up the sweeper skips the excess entries — they will be
-/// picked up again on the next sweep tick.
-const DELETION_CHANNEL_CAPACITY: usize = 10_000;
-
-pub fn router<S>(_state: S) -> Router<S>
+pub fn router<S>(state: S) -> Router<S>
 where
     S: Clone + Send + Sync + 'static,
     State: axum::extract::FromRef<S>,
 {
     let cors = CorsLayer::new()
         .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
         .allow_headers([
             header::CONTENT_TYPE,
             header::HeaderName::from_static("x-paste-encryption-key"),
-        ]);
+            header::HeaderName::from_static("x-paste-deletion-token"),
+            header::HeaderName::from_static("x-paste-edit-token"),
+            header::HeaderName::from_static("x-paste-client-encryption"),
+            header::AUTHORIZATION,
+        ])
+        .expose_headers([header::HeaderName::from_static("x-paste-client-encryption")]);
+
+    let limit_creation = axum::middleware::from_fn_with_state(state, ratelimit::limit_creation);
 
     Router::new()
         .route("/", get(handlers::home))
         .route("/status", get(handlers::status))
-        .route("/new", post(handlers::new_paste))
-        .route("/raw/{key}", get(handlers::view_paste_raw))
+        .route(
+            "/new",
+            post(handlers::new_paste).route_layer(limit_creation.clone()),
+        )
+        .route(
+            "/new/files",
+            post(handlers::new_paste_files).route_layer(limit_creation.clone()),
+        )
+        .route(
+            "/raw/{key}",
+            get(handlers::view_paste_raw).head(handlers::view_paste_raw_head),
+        )
+        .route("/raw/{key}/{filename}", get(handlers::view_paste_file_raw))
+        .route(
+            "/view/{key}",
+            get(handlers::view_highlighted).post(handlers::view_highlighted),
+        )
+        .route(
+            "/api/v1/pastes",
+            post(api::create_paste).route_layer(limit_creation.clone()),
+        )
+        .route("/api/v1/pastes/{key}", get(api::get_paste))
+        .route("/api/v1/pastes/{key}/raw", get(api::get_raw))
+        .route("/api/v1/pastes/{key}/meta", get(api::get_meta))
+        .route("/api/v1/pastes/{key}/revisions", get(api::list_revisions))
+        .route(
+            "/api/v1/pastes/{key}/revisions/{rev}",
+            get(api::get_revision),
+        )
+        .route(
+            "/api/accounts",
+            post(handlers::create_account).route_layer(limit_creation.clone()),
+        )
+        .route(
+            "/api/tokens",
+            post(handlers::create_api_token).delete(handlers::revoke_api_token),
+        )
+        .route(
+            "/api/mine",
+            get(handlers::my_pastes).delete(handlers::delete_my_pastes),
+        )
+        .route("/meta/{key}", get(handlers::paste_meta))
         .route("/json/{key}", get(handlers::view_paste_json))
+        .route("/json/{key}/history", get(handlers::history_json))
+        .route("/json/{key}/rev/{rev}", get(handlers::view_revision_json))
         .route(
             "/{key}",
-            get(handlers::view_paste).post(handlers::view_paste),
+            get(handlers::view_paste)
+                .post(handlers::view_paste)
+                .delete(handlers::delete_paste),
         )
+        .route(
+            "/{key}",
+            put(handlers::edit_paste).route_layer(limit_creation.clone()),
+        )
+        .route("/{key}/history", get(handlers::history))
+        .route(
+            "/{key}/fork",
+            post(handlers::fork_paste).route_layer(limit_creation),
+        )
+        .route(
+            "/{key}/rev/{rev}",
+            get(handlers::view_revision).post(handlers::view_revision),
+        )
+        .route("/{key}/delete", post(handlers::delete_paste_form))
         .nest_service("/static", ServeDir::new("crates/paste/assets/static"))
         .route_service(
             "/favicon.ico",
@@ -55,31 +126,56 @@ where
         .layer(cors)
 }
 
-/// Spawns the background task that processes deletion requests from the
-/// channel.  Each request is handled by [`models::Paste::attempt_deletion`]
-/// which holds a DB transaction open until the S3 deletion is confirmed.
-pub fn init_deletion_worker(
-    state: State,
-    mut rx: tokio::sync::mpsc::Receiver<models::DeletionRequest>,
-) {
-    tokio::spawn(async move {
-        while let Some(req) = rx.recv().await {
-            let paste_id = req.id;
-            match models::Paste::attempt_deletion(&state.db, &state.s3, &state.config, &req).await {
-                Ok(()) => info!("Deleted paste id={paste_id}"),
-                Err(e) => warn!("Failed to delete paste id={paste_id}: {e}"),
+/// Operator endpoints, served on the internal listener at
+/// [`crate::Config::admin_addr`] rather than on the public [`router`].
+pub fn admin_router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    State: axum::extract::FromRef<S>,
+{
+    Router::new()
+        .route("/status/keys", get(handlers::key_status))
+        .route("/metrics", get(handlers::metrics))
+        .route("/reconcile", get(handlers::reconcile_report))
+}
+
+/// How long an idle deletion worker waits before looking for due jobs again.
+const DELETION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Spawns [`crate::Config::deletion_workers`] tasks working through the
+/// deletion queue, see [`deletion::run_next`].  Workers on every instance
+/// share the queue; `SKIP LOCKED` keeps them off each other's jobs.
+pub fn init_deletion_workers(state: State) {
+    for _ in 0..state.config.deletion_workers {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                match deletion::run_next(&state.db, state.store.as_ref(), &state.config).await {
+                    Ok(deletion::JobOutcome::Idle) => {
+                        tokio::time::sleep(DELETION_POLL_INTERVAL).await
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Error running paste deletion job: {e:?}");
+                        tokio::time::sleep(DELETION_POLL_INTERVAL).await;
+                    }
+                }
             }
-        }
-        error!("Paste deletion worker channel closed unexpectedly");
-    });
+        });
+    }
 }
 
 /// Advisory-lock id for the paste sweeper.
 /// Stable numeric encoding of "paste_sw" (first 8 ASCII bytes, big-endian).
 const PASTE_SWEEP_LOCK_ID: i64 = 0x70617374655f7377_u64 as i64;
 
+/// Rate-limit buckets idle this long have refilled and can be dropped; the
+/// slowest bucket refills in an hour.
+const RATE_LIMIT_IDLE: Duration = Duration::from_secs(60 * 60);
+
 /// Spawns the background task that periodically scans for expired / stale
-/// pastes and enqueues them on the deletion channel.
+/// pastes and queues them for deletion, and prunes idle
+/// rate-limit buckets.
 pub fn init_sweeper(state: State) {
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(20));
@@ -112,9 +208,11 @@ pub fn init_sweeper(state: State) {
             };
 
             if !locked {
+                METRICS.sweeper_lock.with_label_values(&["contended"]).inc();
                 debug!("Could not acquire paste_sweep advisory lock, skipping tick");
                 continue;
             }
+            METRICS.sweeper_lock.with_label_values(&["acquired"]).inc();
 
             let result = async {
                 let cutoff = chrono::Utc::now()
@@ -122,13 +220,8 @@ pub fn init_sweeper(state: State) {
                         state.config.max_paste_age_seconds,
                     ))
                     .ok_or_else(|| anyhow::anyhow!("Error calculating stale cutoff date"))?;
-                models::Paste::queue_outdated_for_deletion(
-                    &state.db,
-                    &state.deletion_tx,
-                    cutoff,
-                    chrono::Utc::now(),
-                )
-                .await
+                models::Paste::queue_outdated_for_deletion(&state.db, cutoff, chrono::Utc::now())
+                    .await
             }
             .await;
 
@@ -143,6 +236,12 @@ pub fn init_sweeper(state: State) {
                 Err(e) => error!("Error scanning for stale pastes: {}", e),
             }
 
+            match ratelimit::prune(&state.db, RATE_LIMIT_IDLE).await {
+                Ok(count) if count > 0 => debug!(" ** Pruned {} idle rate-limit buckets **", count),
+                Ok(_) => {}
+                Err(e) => error!("Error pruning rate-limit buckets: {}", e),
+            }
+
             // Release the advisory lock.
             let _ = sqlx::query("select pg_advisory_unlock($1)")
                 .bind(PASTE_SWEEP_LOCK_ID)
@@ -152,23 +251,198 @@ pub fn init_sweeper(state: State) {
     });
 }
 
+/// Revisions handled per [`models::Paste::reencrypt_batch`] call.
+const REENCRYPT_BATCH_SIZE: i64 = 100;
+
+/// Advisory-lock id for the re-encryption job.
+/// Stable numeric encoding of "paste_re" (first 8 ASCII bytes, big-endian).
+const PASTE_REENCRYPT_LOCK_ID: i64 = 0x70617374655f7265_u64 as i64;
+
+/// Spawns the background task that moves blobs encrypted under a retired key
+/// onto the primary key.  Every
+/// [`crate::Config::reencrypt_interval_seconds`], whichever instance takes
+/// the advisory lock makes one pass over every revision not yet recorded as
+/// being on the primary key; progress is kept in the database, so restarts
+/// lose nothing.
+pub fn init_reencryption_job(state: State) {
+    if state.config.reencrypt_interval_seconds == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(state.config.reencrypt_interval_seconds));
+        loop {
+            interval.tick().await;
+
+            let mut conn = match state.db.acquire().await {
+                Ok(c) => c,
+                Err(e) => {
+                    error!("Error acquiring connection for re-encryption lock: {}", e);
+                    continue;
+                }
+            };
+            let locked: bool = match sqlx::query_scalar("select pg_try_advisory_lock($1)")
+                .bind(PASTE_REENCRYPT_LOCK_ID)
+                .fetch_one(&mut *conn)
+                .await
+            {
+                Ok(locked) => locked,
+                Err(e) => {
+                    error!("Error acquiring advisory lock for re-encryption: {}", e);
+                    continue;
+                }
+            };
+            if !locked {
+                debug!("Could not acquire paste_re advisory lock, skipping tick");
+                continue;
+            }
+
+            let mut cursor = (0, 0);
+            loop {
+                match models::Paste::reencrypt_batch(
+                    &state.db,
+                    state.store.as_ref(),
+                    &state.config,
+                    cursor,
+                    REENCRYPT_BATCH_SIZE,
+                )
+                .await
+                {
+                    Ok(Some(next)) => cursor = next,
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("Error re-encrypting pastes: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            if !state.config.retired_encryption_keys.is_empty() {
+                match models::Paste::key_usage(&state.db).await {
+                    Ok(usage) => {
+                        for retired in &state.config.retired_encryption_keys {
+                            let remaining = usage
+                                .iter()
+                                .find(|(id, _)| id.as_deref() == Some(retired.id.as_str()))
+                                .map_or(0, |(_, count)| *count);
+                            info!(
+                                " ** {} blobs remaining on retired key {:?} **",
+                                remaining, retired.id
+                            );
+                        }
+                    }
+                    Err(e) => error!("Error counting blobs per key: {}", e),
+                }
+            }
+
+            let _ = sqlx::query("select pg_advisory_unlock($1)")
+                .bind(PASTE_REENCRYPT_LOCK_ID)
+                .execute(&mut *conn)
+                .await;
+        }
+    });
+}
+
+/// Advisory-lock id for the storage reconciliation job.
+/// Stable numeric encoding of "paste_rc" (first 8 ASCII bytes, big-endian).
+const PASTE_RECONCILE_LOCK_ID: i64 = 0x70617374655f7263_u64 as i64;
+
+/// Spawns the storage reconciliation job, see [`reconcile::reconcile`].  It
+/// runs every [`crate::Config::reconcile_interval_seconds`] on whichever
+/// instance takes the advisory lock.
+pub fn init_reconciliation_job(state: State) {
+    if state.config.reconcile_interval_seconds == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(state.config.reconcile_interval_seconds));
+        loop {
+            interval.tick().await;
+
+            let mut conn = match state.db.acquire().await {
+                Ok(c) => c,
+                Err(e) => {
+                    error!("Error acquiring connection for reconciliation lock: {}", e);
+                    continue;
+                }
+            };
+            let locked: bool = match sqlx::query_scalar("select pg_try_advisory_lock($1)")
+                .bind(PASTE_RECONCILE_LOCK_ID)
+                .fetch_one(&mut *conn)
+                .await
+            {
+                Ok(locked) => locked,
+                Err(e) => {
+                    error!("Error acquiring advisory lock for reconciliation: {}", e);
+                    continue;
+                }
+            };
+            if !locked {
+                debug!("Could not acquire paste_rc advisory lock, skipping tick");
+                continue;
+            }
+
+            let dry_run = state.config.reconcile_dry_run;
+            match reconcile::reconcile(
+                &state.db,
+                state.store.as_ref(),
+                Duration::from_secs(state.config.reconcile_grace_seconds),
+                dry_run,
+            )
+            .await
+            {
+                Ok(report) => {
+                    if dry_run {
+                        for key in &report.orphans {
+                            info!("Reconciliation dry run: would delete orphaned blob {key:?}");
+                        }
+                    }
+                    for missing in &report.missing {
+                        warn!(
+                            "Blob {:?} of paste {} rev {} is missing from storage",
+                            missing.storage_uri, missing.key, missing.rev
+                        );
+                    }
+                    info!(
+                        " ** Reconciled paste storage{}: {} objects, {} orphans {}, {} within grace, {} failed deletes, {} missing blobs **",
+                        if dry_run { " (dry run)" } else { "" },
+                        report.objects,
+                        report.orphans.len(),
+                        if dry_run { "to delete" } else { "deleted" },
+                        report.recent_orphans,
+                        report.delete_failures,
+                        report.missing.len(),
+                    );
+                }
+                Err(e) => error!("Error reconciling paste storage: {}", e),
+            }
+
+            let _ = sqlx::query("select pg_advisory_unlock($1)")
+                .bind(PASTE_RECONCILE_LOCK_ID)
+                .execute(&mut *conn)
+                .await;
+        }
+    });
+}
+
 pub async fn init(config: crate::Config) -> anyhow::Result<State> {
     let db_pool = common::db::init_pool(&config.database_url).await?;
     info!(" ** Established paste database connection pool **");
 
-    let s3 = crate::storage::create_client(&config.s3_endpoint, &config.s3_region).await;
+    let store = crate::storage::init_store(&config).await;
     info!(
-        " ** Created paste S3 client (endpoint: {}) **",
-        config.s3_endpoint
+        " ** Initialized paste blob store (backend: {:?}) **",
+        config.storage_backend
     );
 
     let mut tera = Tera::new("crates/paste/templates/**/*")?;
     tera.autoescape_on(vec!["html"]);
 
-    let (deletion_tx, deletion_rx) = tokio::sync::mpsc::channel(DELETION_CHANNEL_CAPACITY);
-
-    let state = Arc::new(Resources::new(tera, db_pool, config, s3, deletion_tx));
-    init_deletion_worker(state.clone(), deletion_rx);
+    let state = Arc::new(Resources::new(tera, db_pool, config, store));
+    init_deletion_workers(state.clone());
     init_sweeper(state.clone());
+    init_reencryption_job(state.clone());
+    init_reconciliation_job(state.clone());
     Ok(state)
 }
@@ -1,8 +1,11 @@
-//! S3 storage backend for paste content.
+//! Storage for paste content.
+//!
+//! Encoded blobs are written to a [`BlobStore`]: S3 / Tigris ([`S3Store`]),
+//! a local directory ([`FsStore`]), or process memory ([`MemoryStore`]).
 //!
 //! ## Blob format
 //!
-//! Every object stored in S3 is a binary blob with the following layout:
+//! Every stored object is a binary blob with the following layout:
 //!
 //! ```text
 //! $version . $header . $content
@@ -16,16 +19,51 @@
 //!
 //! The [`BlobHeaderV1`] header carries the HMAC signature of the plaintext,
 //! the AES-GCM nonce, and — for user-key-encrypted pastes — the PBKDF2 salt.
+//!
+//! [`BlobHeaderV2`] adds the [`Compression`] algorithm applied to the
+//! plaintext before encryption, the uncompressed length, and the
+//! [`ContentEncoding`].  New blobs are written as V2; V1 blobs stay readable.
+//!
+//! A multi-file paste is a single blob whose plaintext is an archive of
+//! [`PasteFile`]s, see [`ContentEncoding::Files`].
+//!
+//! [`BlobHeaderV3`] marks a client-encrypted paste: `$content` is ciphertext
+//! produced by the uploader, stored as-is, and the header carries the
+//! [`ClientParams`] the uploader needs to decrypt it.  The server never holds
+//! the key.
+//!
+//! [`BlobHeaderV4`] is V2 for pastes encrypted with a user key, recording
+//! the [`Kdf`] and its parameters the key was derived with.  V1 and V2 blobs
+//! don't record one and were all derived with [`Kdf::PBKDF2_LEGACY`].  New
+//! user-key blobs are written as V4, with [`crate::Config::kdf`]; server-key
+//! blobs are still V2.
 
 use anyhow::anyhow;
 use base64::Engine as _;
+use common::crypto::Kdf;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+mod archive;
+mod compression;
+mod fs;
+mod memory;
+mod s3;
+mod timed;
+
+pub use archive::{decode_archive, encode_archive, PasteFile};
+pub use compression::Compression;
+pub use fs::FsStore;
+pub use memory::MemoryStore;
+pub use s3::{create_client, S3Store};
+pub use timed::TimedStore;
+
+use crate::config::StorageBackend;
 
 const B64: base64::engine::general_purpose::GeneralPurpose =
     base64::engine::general_purpose::URL_SAFE_NO_PAD;
-use aws_sdk_s3::primitives::ByteStream;
-use serde::{Deserialize, Serialize};
 
-const CURRENT_VERSION: u32 = 1;
 const SEP: u8 = b'.';
 
 // ---------------------------------------------------------------------------
@@ -38,11 +76,16 @@ struct BlobVersion {
 }
 
 // ---------------------------------------------------------------------------
-// Decrypt trait
+// Header traits
 // ---------------------------------------------------------------------------
 
+/// Implemented by each header struct; ties it to its [`BlobVersion`] number.
+pub trait VersionedHeader: Serialize {
+    const VERSION: u32;
+}
+
 /// Implemented by each versioned header; encapsulates decrypt logic.
-pub trait BlobHeaderDecrypt {
+pub trait BlobHeaderDecrypt: Send + Sync {
     /// Decrypt `ciphertext` and return the plaintext bytes.
     ///
     /// `user_enc_key` is the user-supplied password (bytes).  Pass `None` when
@@ -61,6 +104,127 @@ pub trait BlobHeaderDecrypt {
     /// HMAC-SHA256 hex signature of the original plaintext, for post-decrypt
     /// verification.
     fn sig(&self) -> &str;
+
+    /// ID of the server key the blob is encrypted under, or `None` for blobs
+    /// encrypted with a user-supplied key.
+    fn server_key_id(&self) -> Option<&str>;
+
+    /// The header's [`VersionedHeader::VERSION`].
+    fn version(&self) -> u32;
+
+    /// How to interpret the plaintext, if the header records it.
+    fn content_encoding(&self) -> Option<ContentEncoding> {
+        None
+    }
+
+    /// Parameters for decrypting a client-encrypted blob, whose "plaintext"
+    /// is then the client's ciphertext.
+    fn client_params(&self) -> Option<&ClientParams> {
+        None
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Shared AES-GCM seal / open
+// ---------------------------------------------------------------------------
+
+/// Compress `plaintext` with `compression`, unless that doesn't make it
+/// smaller; returns the compression actually applied.
+fn compress(plaintext: &[u8], compression: Compression) -> anyhow::Result<(Compression, Vec<u8>)> {
+    if compression != Compression::None {
+        let packed = compression.compress(plaintext)?;
+        if packed.len() < plaintext.len() {
+            return Ok((compression, packed));
+        }
+    }
+    Ok((Compression::None, plaintext.to_vec()))
+}
+
+/// Nonce, salt and key id produced by [`seal`], stored in every header version.
+struct Sealed {
+    nonce: String,
+    salt: Option<String>,
+    key_id: Option<String>,
+    ciphertext: Vec<u8>,
+}
+
+/// Encrypt `data` with the user's password (PBKDF2, fresh salt) if given,
+/// otherwise with the server key `key`.
+fn seal(
+    data: &[u8],
+    user_enc_key: Option<&[u8]>,
+    key: common::crypto::KeyRef<'_>,
+    aad: &[u8],
+) -> anyhow::Result<Sealed> {
+    if let Some(user_key) = user_enc_key {
+        let pwenc = common::crypto::encrypt_with_pw_aad(data, user_key, aad)
+            .map_err(|e| anyhow!("encryption error: {e}"))?;
+        Ok(Sealed {
+            nonce: B64.encode(pwenc.nonce()),
+            salt: pwenc.salt().map(|s| B64.encode(s)),
+            key_id: None,
+            ciphertext: pwenc.ciphertext().to_vec(),
+        })
+    } else {
+        let enc = common::crypto::encrypt_with_aad(data, key, aad)
+            .map_err(|e| anyhow!("encryption error: {e}"))?;
+        Ok(Sealed {
+            nonce: B64.encode(enc.nonce()),
+            salt: None,
+            key_id: Some(key.id.to_string()),
+            ciphertext: enc.ciphertext().to_vec(),
+        })
+    }
+}
+
+/// Inverse of [`seal`], driven by the header's `nonce` / `salt` / `key_id`.
+fn open(
+    nonce: &str,
+    salt: Option<&str>,
+    key_id: Option<&str>,
+    ciphertext: &[u8],
+    user_enc_key: Option<&[u8]>,
+    keys: &[common::crypto::KeyRef<'_>],
+    aad: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    if let Some(salt_b64) = salt {
+        let user_key = user_enc_key.ok_or_else(|| anyhow!("decryption failure"))?;
+        let salt = B64
+            .decode(salt_b64)
+            .map_err(|e| anyhow!("base64 salt: {e}"))?;
+        let nonce = B64
+            .decode(nonce)
+            .map_err(|e| anyhow!("base64 nonce: {e}"))?;
+        let pwenc = common::crypto::Encrypted::Pw {
+            ciphertext: ciphertext.to_vec(),
+            nonce,
+            salt,
+        };
+        common::crypto::decrypt_with_pw_aad(&pwenc, user_key, aad)
+            .map_err(|_| anyhow!("failed decrypting content"))
+    } else {
+        let nonce = B64
+            .decode(nonce)
+            .map_err(|e| anyhow!("base64 nonce: {e}"))?;
+        // Legacy blobs without key_id default to "default".
+        let key_id = key_id.unwrap_or("default");
+        let enc = common::crypto::Encrypted::Key {
+            id: key_id.to_string(),
+            ciphertext: ciphertext.to_vec(),
+            nonce,
+        };
+        common::crypto::decrypt_with_aad(&enc, keys, aad).map_err(|_| anyhow!("decryption failure"))
+    }
+}
+
+/// Server key ID recorded by a header's `salt` / `key_id` pair, mirroring
+/// how [`open`] picks a key.
+fn server_key_id<'a>(salt: Option<&str>, key_id: Option<&'a str>) -> Option<&'a str> {
+    match salt {
+        Some(_) => None,
+        // Legacy blobs without key_id default to "default".
+        None => Some(key_id.unwrap_or("default")),
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -70,7 +234,7 @@ pub trait BlobHeaderDecrypt {
 /// Metadata stored in the header section of every V1 S3 blob.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BlobHeaderV1 {
-    /// HMAC-SHA256 hex signature of the **plaintext** content.
+    /// HMAC-SHA256 hex signature of the **plaintext** content bytes.
     pub sig: String,
     /// Base64url-encoded (no padding) AES-GCM nonce (12 bytes).
     pub nonce: String,
@@ -98,33 +262,22 @@ impl BlobHeaderV1 {
         key: common::crypto::KeyRef<'_>,
         aad: &[u8],
     ) -> anyhow::Result<(Self, Vec<u8>)> {
-        let sig =
-            common::crypto::hmac_sign(std::str::from_utf8(plaintext).unwrap_or(""), signing_key);
-
-        if let Some(user_key) = user_enc_key {
-            let pwenc = common::crypto::encrypt_with_pw_aad(plaintext, user_key, aad)
-                .map_err(|e| anyhow!("encryption error: {e}"))?;
-            let header = BlobHeaderV1 {
-                sig,
-                nonce: B64.encode(pwenc.nonce()),
-                salt: pwenc.salt().map(|s| B64.encode(s)),
-                key_id: None,
-            };
-            Ok((header, pwenc.ciphertext().to_vec()))
-        } else {
-            let enc = common::crypto::encrypt_with_aad(plaintext, key, aad)
-                .map_err(|e| anyhow!("encryption error: {e}"))?;
-            let header = BlobHeaderV1 {
-                sig,
-                nonce: B64.encode(enc.nonce()),
-                salt: None,
-                key_id: Some(key.id.to_string()),
-            };
-            Ok((header, enc.ciphertext().to_vec()))
-        }
+        let sig = common::crypto::hmac_sign_bytes(plaintext, signing_key);
+        let sealed = seal(plaintext, user_enc_key, key, aad)?;
+        let header = BlobHeaderV1 {
+            sig,
+            nonce: sealed.nonce,
+            salt: sealed.salt,
+            key_id: sealed.key_id,
+        };
+        Ok((header, sealed.ciphertext))
     }
 }
 
+impl VersionedHeader for BlobHeaderV1 {
+    const VERSION: u32 = 1;
+}
+
 impl BlobHeaderDecrypt for BlobHeaderV1 {
     fn decrypt(
         &self,
@@ -133,52 +286,409 @@ impl BlobHeaderDecrypt for BlobHeaderV1 {
         keys: &[common::crypto::KeyRef<'_>],
         aad: &[u8],
     ) -> anyhow::Result<Vec<u8>> {
-        if let Some(salt_b64) = &self.salt {
-            let user_key = user_enc_key.ok_or_else(|| anyhow!("decryption failure"))?;
-            let salt = B64
-                .decode(salt_b64)
-                .map_err(|e| anyhow!("base64 salt: {e}"))?;
-            let nonce = B64
-                .decode(&self.nonce)
-                .map_err(|e| anyhow!("base64 nonce: {e}"))?;
-            let pwenc = common::crypto::Encrypted::Pw {
-                ciphertext: ciphertext.to_vec(),
-                nonce,
-                salt,
-            };
-            common::crypto::decrypt_with_pw_aad(&pwenc, user_key, aad)
-                .map_err(|_| anyhow!("failed decrypting content"))
+        open(
+            &self.nonce,
+            self.salt.as_deref(),
+            self.key_id.as_deref(),
+            ciphertext,
+            user_enc_key,
+            keys,
+            aad,
+        )
+    }
+
+    fn sig(&self) -> &str {
+        &self.sig
+    }
+
+    fn server_key_id(&self) -> Option<&str> {
+        server_key_id(self.salt.as_deref(), self.key_id.as_deref())
+    }
+
+    fn version(&self) -> u32 {
+        Self::VERSION
+    }
+}
+
+// ---------------------------------------------------------------------------
+// BlobHeaderV2
+// ---------------------------------------------------------------------------
+
+/// How the plaintext bytes of a V2 blob should be interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentEncoding {
+    /// Valid UTF-8 text.
+    Utf8,
+    /// Arbitrary bytes.
+    Binary,
+    /// A multi-file archive, see [`encode_archive`].
+    Files,
+}
+
+impl ContentEncoding {
+    pub fn detect(plaintext: &[u8]) -> Self {
+        if std::str::from_utf8(plaintext).is_ok() {
+            Self::Utf8
         } else {
-            let nonce = B64
-                .decode(&self.nonce)
-                .map_err(|e| anyhow!("base64 nonce: {e}"))?;
-            // Legacy blobs without key_id default to "default".
-            let key_id = self.key_id.as_deref().unwrap_or("default");
-            let enc = common::crypto::Encrypted::Key {
-                id: key_id.to_string(),
-                ciphertext: ciphertext.to_vec(),
-                nonce,
-            };
-            common::crypto::decrypt_with_aad(&enc, keys, aad)
-                .map_err(|_| anyhow!("decryption failure"))
+            Self::Binary
         }
     }
+}
+
+/// V2 header: V1 plus compression applied to the plaintext before AES-GCM.
+///
+/// `sig` still covers the **uncompressed** plaintext, so signatures don't
+/// depend on the compressor's output.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlobHeaderV2 {
+    /// HMAC-SHA256 hex signature of the uncompressed plaintext bytes.
+    pub sig: String,
+    /// Base64url-encoded (no padding) AES-GCM nonce (12 bytes).
+    pub nonce: String,
+    /// Base64url-encoded (no padding) PBKDF2 salt; `None` for server-key blobs.
+    pub salt: Option<String>,
+    /// ID of the server-side key used to encrypt this blob.
+    pub key_id: Option<String>,
+    /// Compression applied before encryption.
+    pub compression: Compression,
+    /// Length of the uncompressed plaintext in bytes.
+    pub plaintext_len: u64,
+    /// Whether the plaintext is UTF-8 text, arbitrary bytes or an archive.
+    pub content_encoding: ContentEncoding,
+}
+
+impl BlobHeaderV2 {
+    /// Compress `plaintext` with `compression`, then encrypt as
+    /// [`BlobHeaderV1::encrypt`] does.
+    ///
+    /// If compressing doesn't make the payload smaller it is stored
+    /// uncompressed and the header records [`Compression::None`].
+    pub fn encrypt(
+        plaintext: &[u8],
+        signing_key: &[u8],
+        compression: Compression,
+        user_enc_key: Option<&[u8]>,
+        key: common::crypto::KeyRef<'_>,
+        aad: &[u8],
+    ) -> anyhow::Result<(Self, Vec<u8>)> {
+        let encoding = ContentEncoding::detect(plaintext);
+        Self::encrypt_as(
+            plaintext,
+            encoding,
+            signing_key,
+            compression,
+            user_enc_key,
+            key,
+            aad,
+        )
+    }
+
+    /// Encrypt `files` as a multi-file archive, see [`BlobHeaderV2::encrypt`].
+    pub fn encrypt_archive(
+        files: &[PasteFile],
+        signing_key: &[u8],
+        compression: Compression,
+        user_enc_key: Option<&[u8]>,
+        key: common::crypto::KeyRef<'_>,
+        aad: &[u8],
+    ) -> anyhow::Result<(Self, Vec<u8>)> {
+        let plaintext = encode_archive(files)?;
+        Self::encrypt_as(
+            &plaintext,
+            ContentEncoding::Files,
+            signing_key,
+            compression,
+            user_enc_key,
+            key,
+            aad,
+        )
+    }
+
+    fn encrypt_as(
+        plaintext: &[u8],
+        content_encoding: ContentEncoding,
+        signing_key: &[u8],
+        compression: Compression,
+        user_enc_key: Option<&[u8]>,
+        key: common::crypto::KeyRef<'_>,
+        aad: &[u8],
+    ) -> anyhow::Result<(Self, Vec<u8>)> {
+        let sig = common::crypto::hmac_sign_bytes(plaintext, signing_key);
+        let (compression, payload) = compress(plaintext, compression)?;
+        let sealed = seal(&payload, user_enc_key, key, aad)?;
+        let header = BlobHeaderV2 {
+            sig,
+            nonce: sealed.nonce,
+            salt: sealed.salt,
+            key_id: sealed.key_id,
+            compression,
+            plaintext_len: plaintext.len() as u64,
+            content_encoding,
+        };
+        Ok((header, sealed.ciphertext))
+    }
+}
+
+impl VersionedHeader for BlobHeaderV2 {
+    const VERSION: u32 = 2;
+}
+
+impl BlobHeaderDecrypt for BlobHeaderV2 {
+    fn decrypt(
+        &self,
+        ciphertext: &[u8],
+        user_enc_key: Option<&[u8]>,
+        keys: &[common::crypto::KeyRef<'_>],
+        aad: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let payload = open(
+            &self.nonce,
+            self.salt.as_deref(),
+            self.key_id.as_deref(),
+            ciphertext,
+            user_enc_key,
+            keys,
+            aad,
+        )?;
+        self.compression.decompress(&payload, self.plaintext_len)
+    }
 
     fn sig(&self) -> &str {
         &self.sig
     }
+
+    fn server_key_id(&self) -> Option<&str> {
+        server_key_id(self.salt.as_deref(), self.key_id.as_deref())
+    }
+
+    fn version(&self) -> u32 {
+        Self::VERSION
+    }
+
+    fn content_encoding(&self) -> Option<ContentEncoding> {
+        Some(self.content_encoding)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// BlobHeaderV3
+// ---------------------------------------------------------------------------
+
+/// Decryption parameters of a client-encrypted paste (algorithm, IV, KDF
+/// settings, ...).  Opaque to the server beyond basic size checks; the
+/// browser client writes `alg` = `"AES-GCM"` and a base64url `iv`.
+pub type ClientParams = BTreeMap<String, String>;
+
+/// V3 header: content encrypted by the client before upload.
+///
+/// The server can't decrypt V3 content, so it is neither compressed nor
+/// wrapped in a server key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlobHeaderV3 {
+    /// HMAC-SHA256 hex signature of the client ciphertext.
+    pub sig: String,
+    /// Client-supplied decryption parameters.
+    pub client_params: ClientParams,
+}
+
+impl BlobHeaderV3 {
+    /// Sign `ciphertext` and produce a `(BlobHeaderV3, ciphertext)` pair.
+    pub fn seal(
+        ciphertext: &[u8],
+        signing_key: &[u8],
+        client_params: ClientParams,
+    ) -> (Self, Vec<u8>) {
+        let header = BlobHeaderV3 {
+            sig: common::crypto::hmac_sign_bytes(ciphertext, signing_key),
+            client_params,
+        };
+        (header, ciphertext.to_vec())
+    }
+}
+
+impl VersionedHeader for BlobHeaderV3 {
+    const VERSION: u32 = 3;
+}
+
+impl BlobHeaderDecrypt for BlobHeaderV3 {
+    /// Returns the client ciphertext unchanged; no server or user key
+    /// applies.
+    fn decrypt(
+        &self,
+        ciphertext: &[u8],
+        _user_enc_key: Option<&[u8]>,
+        _keys: &[common::crypto::KeyRef<'_>],
+        _aad: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        Ok(ciphertext.to_vec())
+    }
+
+    fn sig(&self) -> &str {
+        &self.sig
+    }
+
+    fn server_key_id(&self) -> Option<&str> {
+        None
+    }
+
+    fn version(&self) -> u32 {
+        Self::VERSION
+    }
+
+    fn client_params(&self) -> Option<&ClientParams> {
+        Some(&self.client_params)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// BlobHeaderV4
+// ---------------------------------------------------------------------------
+
+/// V4 header: a V2 blob encrypted with a user key, plus the [`Kdf`] the key
+/// was derived with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlobHeaderV4 {
+    /// HMAC-SHA256 hex signature of the uncompressed plaintext bytes.
+    pub sig: String,
+    /// Base64url-encoded (no padding) AES-GCM nonce (12 bytes).
+    pub nonce: String,
+    /// Base64url-encoded (no padding) KDF salt (64 bytes).
+    pub salt: String,
+    /// Key derivation function and parameters applied to the user key.
+    pub kdf: Kdf,
+    /// Compression applied before encryption.
+    pub compression: Compression,
+    /// Length of the uncompressed plaintext in bytes.
+    pub plaintext_len: u64,
+    /// Whether the plaintext is UTF-8 text, arbitrary bytes or an archive.
+    pub content_encoding: ContentEncoding,
+}
+
+impl BlobHeaderV4 {
+    /// Compress `plaintext` as [`BlobHeaderV2::encrypt`] does, then encrypt
+    /// it with a key derived from `user_enc_key` by `kdf`, with a fresh salt.
+    pub fn encrypt(
+        plaintext: &[u8],
+        signing_key: &[u8],
+        compression: Compression,
+        user_enc_key: &[u8],
+        kdf: Kdf,
+        aad: &[u8],
+    ) -> anyhow::Result<(Self, Vec<u8>)> {
+        let encoding = ContentEncoding::detect(plaintext);
+        Self::encrypt_as(
+            plaintext,
+            encoding,
+            signing_key,
+            compression,
+            user_enc_key,
+            kdf,
+            aad,
+        )
+    }
+
+    /// Encrypt `files` as a multi-file archive, see [`BlobHeaderV4::encrypt`].
+    pub fn encrypt_archive(
+        files: &[PasteFile],
+        signing_key: &[u8],
+        compression: Compression,
+        user_enc_key: &[u8],
+        kdf: Kdf,
+        aad: &[u8],
+    ) -> anyhow::Result<(Self, Vec<u8>)> {
+        let plaintext = encode_archive(files)?;
+        Self::encrypt_as(
+            &plaintext,
+            ContentEncoding::Files,
+            signing_key,
+            compression,
+            user_enc_key,
+            kdf,
+            aad,
+        )
+    }
+
+    fn encrypt_as(
+        plaintext: &[u8],
+        content_encoding: ContentEncoding,
+        signing_key: &[u8],
+        compression: Compression,
+        user_enc_key: &[u8],
+        kdf: Kdf,
+        aad: &[u8],
+    ) -> anyhow::Result<(Self, Vec<u8>)> {
+        let sig = common::crypto::hmac_sign_bytes(plaintext, signing_key);
+        let (compression, payload) = compress(plaintext, compression)?;
+        let pwenc = common::crypto::encrypt_with_pw_kdf_aad(&payload, user_enc_key, &kdf, aad)
+            .map_err(|e| anyhow!("encryption error: {e}"))?;
+        let header = BlobHeaderV4 {
+            sig,
+            nonce: B64.encode(pwenc.nonce()),
+            salt: B64.encode(pwenc.salt().unwrap_or_default()),
+            kdf,
+            compression,
+            plaintext_len: plaintext.len() as u64,
+            content_encoding,
+        };
+        Ok((header, pwenc.ciphertext().to_vec()))
+    }
+}
+
+impl VersionedHeader for BlobHeaderV4 {
+    const VERSION: u32 = 4;
+}
+
+impl BlobHeaderDecrypt for BlobHeaderV4 {
+    fn decrypt(
+        &self,
+        ciphertext: &[u8],
+        user_enc_key: Option<&[u8]>,
+        _keys: &[common::crypto::KeyRef<'_>],
+        aad: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let user_key = user_enc_key.ok_or_else(|| anyhow!("decryption failure"))?;
+        let pwenc = common::crypto::Encrypted::Pw {
+            ciphertext: ciphertext.to_vec(),
+            nonce: B64
+                .decode(&self.nonce)
+                .map_err(|e| anyhow!("base64 nonce: {e}"))?,
+            salt: B64
+                .decode(&self.salt)
+                .map_err(|e| anyhow!("base64 salt: {e}"))?,
+        };
+        let payload = common::crypto::decrypt_with_pw_kdf_aad(&pwenc, user_key, &self.kdf, aad)
+            .map_err(|_| anyhow!("failed decrypting content"))?;
+        self.compression.decompress(&payload, self.plaintext_len)
+    }
+
+    fn sig(&self) -> &str {
+        &self.sig
+    }
+
+    fn server_key_id(&self) -> Option<&str> {
+        None
+    }
+
+    fn version(&self) -> u32 {
+        Self::VERSION
+    }
+
+    fn content_encoding(&self) -> Option<ContentEncoding> {
+        Some(self.content_encoding)
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Encode / decode
 // ---------------------------------------------------------------------------
 
-/// Encode a `(BlobHeaderV1, ciphertext)` pair into the blob format.
+/// Encode a `(header, ciphertext)` pair into the blob format.
 ///
 /// Output: `base64url(msgpack(BlobVersion)) . base64url(msgpack(header)) . ciphertext`
-pub fn encode_blob(header: &BlobHeaderV1, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+pub fn encode_blob<H: VersionedHeader>(header: &H, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
     let version_bytes = rmp_serde::to_vec(&BlobVersion {
-        version: CURRENT_VERSION,
+        version: H::VERSION,
     })
     .map_err(|e| anyhow!("msgpack version: {e}"))?;
     let header_bytes = rmp_serde::to_vec(header).map_err(|e| anyhow!("msgpack header: {e}"))?;
@@ -233,6 +743,21 @@ pub fn decode_blob(blob: &[u8]) -> anyhow::Result<(Box<dyn BlobHeaderDecrypt>, V
                 .map_err(|e| anyhow!("msgpack header v1: {e}"))?;
             Box::new(h)
         }
+        2 => {
+            let h: BlobHeaderV2 = rmp_serde::from_slice(&header_bytes)
+                .map_err(|e| anyhow!("msgpack header v2: {e}"))?;
+            Box::new(h)
+        }
+        3 => {
+            let h: BlobHeaderV3 = rmp_serde::from_slice(&header_bytes)
+                .map_err(|e| anyhow!("msgpack header v3: {e}"))?;
+            Box::new(h)
+        }
+        4 => {
+            let h: BlobHeaderV4 = rmp_serde::from_slice(&header_bytes)
+                .map_err(|e| anyhow!("msgpack header v4: {e}"))?;
+            Box::new(h)
+        }
         v => return Err(anyhow!("unsupported blob version: {v}")),
     };
 
@@ -240,78 +765,70 @@ pub fn decode_blob(blob: &[u8]) -> anyhow::Result<(Box<dyn BlobHeaderDecrypt>, V
 }
 
 // ---------------------------------------------------------------------------
-// S3 operations
+// Blob stores
 // ---------------------------------------------------------------------------
 
-/// Upload `data` to `bucket` at `key`, replacing any existing object.
-pub async fn put_object(
-    client: &aws_sdk_s3::Client,
-    bucket: &str,
-    key: &str,
-    data: Vec<u8>,
-) -> anyhow::Result<()> {
-    client
-        .put_object()
-        .bucket(bucket)
-        .key(key)
-        .body(ByteStream::from(data))
-        .send()
-        .await
-        .map_err(|e| anyhow!("S3 put_object error for key {key:?}: {e}"))?;
-    Ok(())
-}
-
-/// Download the object at `key` from `bucket` and return its bytes.
-pub async fn get_object(
-    client: &aws_sdk_s3::Client,
-    bucket: &str,
-    key: &str,
-) -> anyhow::Result<Vec<u8>> {
-    let resp = client
-        .get_object()
-        .bucket(bucket)
-        .key(key)
-        .send()
-        .await
-        .map_err(|e| anyhow!("S3 get_object error for key {key:?}: {e}"))?;
-    let bytes = resp
-        .body
-        .collect()
-        .await
-        .map_err(|e| anyhow!("S3 read body error for key {key:?}: {e}"))?
-        .into_bytes();
-    Ok(bytes.to_vec())
-}
-
-/// Delete the object at `key` from `bucket`.  A missing object is not an error.
-pub async fn delete_object(
-    client: &aws_sdk_s3::Client,
-    bucket: &str,
-    key: &str,
-) -> anyhow::Result<()> {
-    client
-        .delete_object()
-        .bucket(bucket)
-        .key(key)
-        .send()
-        .await
-        .map_err(|e| anyhow!("S3 delete_object error for key {key:?}: {e}"))?;
-    Ok(())
-}
-
-/// Build an S3 client pointed at the given custom endpoint.
+/// An object in a [`BlobStore`] listing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredObject {
+    pub key: String,
+    pub last_modified: chrono::DateTime<chrono::Utc>,
+}
+
+/// One page of [`BlobStore::list`].
+#[derive(Debug, Default)]
+pub struct ObjectPage {
+    pub objects: Vec<StoredObject>,
+    /// Pass to the next [`BlobStore::list`] call; `None` on the last page.
+    pub next: Option<String>,
+}
+
+/// Error from [`BlobStore::get`] when there is no object at the key.  Check
+/// for it with [`anyhow::Error::is`].
+#[derive(Debug)]
+pub struct ObjectNotFound(pub String);
+
+impl std::fmt::Display for ObjectNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no object stored at {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ObjectNotFound {}
+
+/// Object storage for encoded paste blobs.
 ///
-/// Credentials are read from the standard AWS environment variables
-/// (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`).
-pub async fn create_client(endpoint_url: &str, region: &str) -> aws_sdk_s3::Client {
-    let sdk_config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12())
-        .endpoint_url(endpoint_url)
-        .load()
-        .await;
-    let s3_config = aws_sdk_s3::config::Builder::from(&sdk_config)
-        .region(aws_sdk_s3::config::Region::new(region.to_owned()))
-        .build();
-    aws_sdk_s3::Client::from_conf(s3_config)
+/// Keys are the `storage_uri` values recorded on each `pastes` row.  The
+/// backend in use is selected by [`Config::storage_backend`](crate::Config).
+#[async_trait::async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Upload `data` at `key`, replacing any existing object.
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()>;
+
+    /// Download the object at `key` and return its bytes.  Fails with
+    /// [`ObjectNotFound`] if there is none.
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>>;
+
+    /// Delete the object at `key`.  A missing object is not an error.
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+
+    /// List stored objects a page at a time, starting from `continuation`,
+    /// the `next` of the previous page, or from the beginning if `None`.
+    async fn list(&self, continuation: Option<String>) -> anyhow::Result<ObjectPage>;
+}
+
+/// Build the [`BlobStore`] selected by `config.storage_backend`, timed by a
+/// [`TimedStore`].
+pub async fn init_store(config: &crate::Config) -> Arc<dyn BlobStore> {
+    let (store, backend): (Arc<dyn BlobStore>, _) = match config.storage_backend {
+        StorageBackend::S3 => {
+            let client = s3::create_client(&config.s3_endpoint, &config.s3_region).await;
+            (Arc::new(S3Store::new(client, &config.s3_bucket)), "s3")
+        }
+        StorageBackend::Fs => (Arc::new(FsStore::new(&config.storage_dir)), "fs"),
+        StorageBackend::Memory => (Arc::new(MemoryStore::default()), "memory"),
+    };
+    Arc::new(TimedStore::new(store, backend))
 }
 
 // ---------------------------------------------------------------------------
@@ -447,6 +964,122 @@ mod tests {
         assert_eq!(plain, plaintext);
     }
 
+    #[test]
+    fn v2_encrypt_decrypt_roundtrip_all_compressions() {
+        let plaintext = b"log line, log line, log line, log line\n".repeat(50);
+        let signing_key = b"signing-key-32-bytes-padding-xxx";
+        let aes_key_bytes = common::crypto::sha256(b"config-enc-key");
+        let aes_key = common::crypto::KeyRef {
+            id: "default",
+            key: &aes_key_bytes,
+        };
+        let aad = 4i32.to_be_bytes();
+
+        for alg in [Compression::None, Compression::Zstd, Compression::Deflate] {
+            for user_key in [None, Some(&b"pw"[..])] {
+                let (header, ct) =
+                    BlobHeaderV2::encrypt(&plaintext, signing_key, alg, user_key, aes_key, &aad)
+                        .unwrap();
+                assert_eq!(header.compression, alg);
+                assert_eq!(header.plaintext_len, plaintext.len() as u64);
+                assert_eq!(header.content_encoding, ContentEncoding::Utf8);
+                let blob = encode_blob(&header, &ct).unwrap();
+                let (dec_header, dec_ct) = decode_blob(&blob).unwrap();
+                let plain = dec_header
+                    .decrypt(&dec_ct, user_key, &[aes_key], &aad)
+                    .unwrap();
+                assert_eq!(plain, plaintext);
+                assert_eq!(dec_header.sig(), header.sig);
+            }
+        }
+    }
+
+    #[test]
+    fn v2_compression_shrinks_ciphertext() {
+        let plaintext = b"{\"k\":\"v\"}\n".repeat(500);
+        let aes_key_bytes = common::crypto::sha256(b"config-enc-key");
+        let aes_key = common::crypto::KeyRef {
+            id: "default",
+            key: &aes_key_bytes,
+        };
+        let (_, ct) =
+            BlobHeaderV2::encrypt(&plaintext, b"s", Compression::Zstd, None, aes_key, b"").unwrap();
+        assert!(ct.len() < plaintext.len() / 10);
+    }
+
+    #[test]
+    fn v2_incompressible_input_stored_uncompressed() {
+        let plaintext = common::crypto::rand_bytes(256).unwrap();
+        let aes_key_bytes = common::crypto::sha256(b"config-enc-key");
+        let aes_key = common::crypto::KeyRef {
+            id: "default",
+            key: &aes_key_bytes,
+        };
+        let (header, _) =
+            BlobHeaderV2::encrypt(&plaintext, b"s", Compression::Zstd, None, aes_key, b"").unwrap();
+        assert_eq!(header.compression, Compression::None);
+        assert_eq!(header.content_encoding, ContentEncoding::Binary);
+    }
+
+    #[test]
+    fn v2_archive_roundtrip_marks_encoding() {
+        let files = vec![PasteFile {
+            name: "main.rs".to_string(),
+            content_type: "rust".to_string(),
+            mime_type: "text/plain; charset=utf-8".to_string(),
+            content: b"fn main() {}\n".to_vec(),
+        }];
+        let aes_key_bytes = common::crypto::sha256(b"config-enc-key");
+        let aes_key = common::crypto::KeyRef {
+            id: "default",
+            key: &aes_key_bytes,
+        };
+        let (header, ct) =
+            BlobHeaderV2::encrypt_archive(&files, b"s", Compression::Zstd, None, aes_key, b"")
+                .unwrap();
+        let blob = encode_blob(&header, &ct).unwrap();
+        let (dec_header, dec_ct) = decode_blob(&blob).unwrap();
+        assert_eq!(dec_header.content_encoding(), Some(ContentEncoding::Files));
+        let plain = dec_header.decrypt(&dec_ct, None, &[aes_key], b"").unwrap();
+        assert_eq!(decode_archive(&plain).unwrap(), files);
+    }
+
+    #[test]
+    fn v3_blob_keeps_client_ciphertext() {
+        let params = ClientParams::from([
+            ("alg".to_string(), "AES-GCM".to_string()),
+            ("iv".to_string(), "AAAAAAAAAAAAAAAA".to_string()),
+        ]);
+        let (header, ct) = BlobHeaderV3::seal(b"\x00opaque\xff", b"s", params.clone());
+        let blob = encode_blob(&header, &ct).unwrap();
+        let (dec_header, dec_ct) = decode_blob(&blob).unwrap();
+        assert_eq!(dec_header.client_params(), Some(&params));
+        assert_eq!(dec_header.server_key_id(), None);
+        let plain = dec_header.decrypt(&dec_ct, None, &[], b"aad").unwrap();
+        assert_eq!(plain, b"\x00opaque\xff");
+        assert!(common::crypto::hmac_verify_bytes(
+            &plain,
+            dec_header.sig(),
+            b"s"
+        ));
+    }
+
+    #[test]
+    fn v1_blob_still_decodes_alongside_v2() {
+        let aes_key_bytes = common::crypto::sha256(b"config-enc-key");
+        let aes_key = common::crypto::KeyRef {
+            id: "default",
+            key: &aes_key_bytes,
+        };
+        let (header, ct) = BlobHeaderV1::encrypt(b"old", b"s", None, aes_key, b"aad").unwrap();
+        let blob = encode_blob(&header, &ct).unwrap();
+        let (dec_header, dec_ct) = decode_blob(&blob).unwrap();
+        let plain = dec_header
+            .decrypt(&dec_ct, None, &[aes_key], b"aad")
+            .unwrap();
+        assert_eq!(plain, b"old");
+    }
+
     #[test]
     fn decrypt_user_key_requires_key() {
         let plaintext = b"needs a key";
@@ -470,4 +1103,57 @@ mod tests {
             .to_string()
             .contains("decryption failure"));
     }
+
+    /// Cheap enough for unoptimised test builds.
+    const TEST_KDF: Kdf = Kdf::Argon2id {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn v4_records_kdf_and_roundtrips() {
+        let plaintext = b"argon2 protected ".repeat(20);
+        let aad = 4i32.to_be_bytes();
+        let (header, ct) =
+            BlobHeaderV4::encrypt(&plaintext, b"s", Compression::Zstd, b"pw", TEST_KDF, &aad)
+                .unwrap();
+        assert_eq!(header.kdf, TEST_KDF);
+        assert_eq!(header.compression, Compression::Zstd);
+        let blob = encode_blob(&header, &ct).unwrap();
+        let (dec_header, dec_ct) = decode_blob(&blob).unwrap();
+        assert_eq!(dec_header.version(), BlobHeaderV4::VERSION);
+        assert_eq!(dec_header.server_key_id(), None);
+        assert_eq!(dec_header.content_encoding(), Some(ContentEncoding::Utf8));
+        let plain = dec_header.decrypt(&dec_ct, Some(b"pw"), &[], &aad).unwrap();
+        assert_eq!(plain, plaintext);
+        assert!(dec_header
+            .decrypt(&dec_ct, Some(b"nope"), &[], &aad)
+            .is_err());
+        assert!(dec_header.decrypt(&dec_ct, None, &[], &aad).is_err());
+    }
+
+    #[test]
+    fn v2_user_key_blob_still_decrypts_with_pbkdf2() {
+        let aes_key_bytes = common::crypto::sha256(b"config-enc-key");
+        let aes_key = common::crypto::KeyRef {
+            id: "default",
+            key: &aes_key_bytes,
+        };
+        let (header, ct) = BlobHeaderV2::encrypt(
+            b"old secret",
+            b"s",
+            Compression::None,
+            Some(b"pw"),
+            aes_key,
+            b"aad",
+        )
+        .unwrap();
+        let blob = encode_blob(&header, &ct).unwrap();
+        let (dec_header, dec_ct) = decode_blob(&blob).unwrap();
+        let plain = dec_header
+            .decrypt(&dec_ct, Some(b"pw"), &[aes_key], b"aad")
+            .unwrap();
+        assert_eq!(plain, b"old secret");
+    }
 }
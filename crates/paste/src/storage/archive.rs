@@ -0,0 +1,67 @@
+//! Multi-file paste archives.
+//!
+//! A paste holding several named files stores them in a single blob whose
+//! plaintext is a msgpack-serialised list of [`PasteFile`]s, marked with
+//! [`super::ContentEncoding::Files`] in the header.  The whole archive is
+//! compressed, encrypted and signed like any other plaintext.
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+/// One named file in a multi-file paste.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PasteFile {
+    pub name: String,
+    /// Syntax type, one of `CONTENT_TYPES`.
+    pub content_type: String,
+    pub mime_type: String,
+    #[serde(with = "serde_bytes")]
+    pub content: Vec<u8>,
+}
+
+impl PasteFile {
+    /// The content as a `&str`, or `None` if it isn't valid UTF-8.
+    pub fn content_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.content).ok()
+    }
+}
+
+/// Serialise `files` into archive plaintext.
+pub fn encode_archive(files: &[PasteFile]) -> anyhow::Result<Vec<u8>> {
+    rmp_serde::to_vec_named(files).map_err(|e| anyhow!("archive encode: {e}"))
+}
+
+/// Parse archive plaintext produced by [`encode_archive`].
+pub fn decode_archive(plaintext: &[u8]) -> anyhow::Result<Vec<PasteFile>> {
+    rmp_serde::from_slice(plaintext).map_err(|e| anyhow!("archive decode: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, content: &[u8]) -> PasteFile {
+        PasteFile {
+            name: name.to_string(),
+            content_type: "text".to_string(),
+            mime_type: "text/plain; charset=utf-8".to_string(),
+            content: content.to_vec(),
+        }
+    }
+
+    #[test]
+    fn archive_roundtrip() {
+        let files = vec![
+            file("Cargo.toml", b"[package]\nname = \"repro\"\n"),
+            file("main.rs", b"fn main() {}\n"),
+            file("blob.bin", &[0, 159, 146, 150]),
+        ];
+        let plain = encode_archive(&files).unwrap();
+        assert_eq!(decode_archive(&plain).unwrap(), files);
+    }
+
+    #[test]
+    fn decode_rejects_garbage() {
+        assert!(decode_archive(b"definitely not msgpack").is_err());
+    }
+}
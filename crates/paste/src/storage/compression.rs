@@ -0,0 +1,122 @@
+//! Compression applied to plaintext before encryption in V2 blobs.
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// zstd level used for new blobs.  Level 3 is zstd's default and a good
+/// speed/ratio trade-off for text.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression algorithm recorded in [`super::BlobHeaderV2`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Zstd,
+    Deflate,
+}
+
+impl Compression {
+    /// Parse an algorithm name, falling back to [`Compression::Zstd`] for
+    /// anything unrecognised.
+    pub fn parse(s: &str) -> Self {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" | "off" => Self::None,
+            "deflate" => Self::Deflate,
+            _ => Self::Zstd,
+        }
+    }
+
+    /// Compress `plaintext`.
+    pub fn compress(self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(plaintext.to_vec()),
+            Self::Zstd => zstd::bulk::compress(plaintext, ZSTD_LEVEL)
+                .map_err(|e| anyhow!("zstd compress: {e}")),
+            Self::Deflate => {
+                let mut enc = flate2::write::DeflateEncoder::new(
+                    Vec::with_capacity(plaintext.len() / 2),
+                    flate2::Compression::default(),
+                );
+                enc.write_all(plaintext)
+                    .map_err(|e| anyhow!("deflate compress: {e}"))?;
+                enc.finish().map_err(|e| anyhow!("deflate compress: {e}"))
+            }
+        }
+    }
+
+    /// Decompress `data`, which must expand to exactly `plaintext_len` bytes.
+    ///
+    /// The output is capped at `plaintext_len` so a corrupt or hostile header
+    /// can't be used to inflate an arbitrarily large buffer.
+    pub fn decompress(self, data: &[u8], plaintext_len: u64) -> anyhow::Result<Vec<u8>> {
+        let len = usize::try_from(plaintext_len)
+            .map_err(|_| anyhow!("plaintext length {plaintext_len} too large"))?;
+        let out = match self {
+            Self::None => data.to_vec(),
+            Self::Zstd => {
+                zstd::bulk::decompress(data, len).map_err(|e| anyhow!("zstd decompress: {e}"))?
+            }
+            Self::Deflate => {
+                let mut out = Vec::with_capacity(len);
+                flate2::read::DeflateDecoder::new(data)
+                    .take(plaintext_len + 1)
+                    .read_to_end(&mut out)
+                    .map_err(|e| anyhow!("deflate decompress: {e}"))?;
+                out
+            }
+        };
+        if out.len() != len {
+            return Err(anyhow!(
+                "decompressed length {} does not match header length {len}",
+                out.len()
+            ));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        b"{\"level\":\"info\",\"msg\":\"hello\"}\n".repeat(200)
+    }
+
+    #[test]
+    fn roundtrip_all_algorithms() {
+        let plain = sample();
+        for alg in [Compression::None, Compression::Zstd, Compression::Deflate] {
+            let packed = alg.compress(&plain).unwrap();
+            let unpacked = alg.decompress(&packed, plain.len() as u64).unwrap();
+            assert_eq!(unpacked, plain, "{alg:?} roundtrip");
+        }
+    }
+
+    #[test]
+    fn compression_shrinks_repetitive_input() {
+        let plain = sample();
+        for alg in [Compression::Zstd, Compression::Deflate] {
+            assert!(alg.compress(&plain).unwrap().len() < plain.len() / 5);
+        }
+    }
+
+    #[test]
+    fn decompress_rejects_length_mismatch() {
+        let plain = sample();
+        for alg in [Compression::None, Compression::Zstd, Compression::Deflate] {
+            let packed = alg.compress(&plain).unwrap();
+            assert!(alg.decompress(&packed, plain.len() as u64 - 1).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_defaults_to_zstd() {
+        assert_eq!(Compression::parse("deflate"), Compression::Deflate);
+        assert_eq!(Compression::parse("NONE"), Compression::None);
+        assert_eq!(Compression::parse("zstd"), Compression::Zstd);
+        assert_eq!(Compression::parse("bogus"), Compression::Zstd);
+    }
+}
@@ -0,0 +1,225 @@
+//! Local-directory [`BlobStore`] backend.
+//!
+//! Each blob is written to `$root/$key`.  Writes go to a uniquely named
+//! temporary sibling file first and are renamed into place so readers never
+//! observe a partial blob, and concurrent writes to one key never share a
+//! temporary file.  Listings walk the whole directory, skip in-progress
+//! writes and come back in a single page.
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use std::path::{Component, Path, PathBuf};
+
+use super::{BlobStore, ObjectNotFound, ObjectPage, StoredObject};
+
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Resolve `key` beneath the store root, rejecting anything that could
+    /// escape it (absolute paths, `..`, etc.).
+    fn path_for(&self, key: &str) -> anyhow::Result<PathBuf> {
+        let rel = Path::new(key);
+        let is_plain =
+            !key.is_empty() && rel.components().all(|c| matches!(c, Component::Normal(_)));
+        if !is_plain {
+            return Err(anyhow!("invalid storage key {key:?}"));
+        }
+        Ok(self.root.join(rel))
+    }
+}
+
+/// A fresh `{file_name}.{random}.tmp` sibling of `path` to write into.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{name}.{:016x}.tmp", rand::random::<u64>()))
+}
+
+/// Whether `name` is one of [`temp_path`]'s in-progress files.
+fn is_temp(name: &str) -> bool {
+    name.strip_suffix(".tmp")
+        .and_then(|rest| rest.rsplit_once('.'))
+        .is_some_and(|(_, random)| {
+            random.len() == 16 && random.bytes().all(|b| b.is_ascii_hexdigit())
+        })
+}
+
+#[async_trait::async_trait]
+impl BlobStore for FsStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| anyhow!("fs create_dir error for key {key:?}: {e}"))?;
+        }
+        let tmp = temp_path(&path);
+        tokio::fs::write(&tmp, data)
+            .await
+            .map_err(|e| anyhow!("fs write error for key {key:?}: {e}"))?;
+        if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(anyhow!("fs rename error for key {key:?}: {e}"));
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        let path = self.path_for(key)?;
+        tokio::fs::read(&path).await.map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                ObjectNotFound(key.to_string()).into()
+            } else {
+                anyhow!("fs read error for key {key:?}: {e}")
+            }
+        })
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let path = self.path_for(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(anyhow!("fs delete error for key {key:?}: {e}")),
+        }
+    }
+
+    async fn list(&self, _continuation: Option<String>) -> anyhow::Result<ObjectPage> {
+        let mut objects = Vec::new();
+        let mut dirs = vec![self.root.clone()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                // Nothing has been stored yet.
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(anyhow!("fs list error in {dir:?}: {e}")),
+            };
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|e| anyhow!("fs list error in {dir:?}: {e}"))?
+            {
+                let path = entry.path();
+                let meta = entry
+                    .metadata()
+                    .await
+                    .map_err(|e| anyhow!("fs stat error for {path:?}: {e}"))?;
+                if meta.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if entry.file_name().to_str().is_some_and(is_temp) {
+                    continue;
+                }
+                let Some(key) = path
+                    .strip_prefix(&self.root)
+                    .ok()
+                    .and_then(Path::to_str)
+                    .map(|key| key.replace(std::path::MAIN_SEPARATOR, "/"))
+                else {
+                    continue;
+                };
+                let modified = meta
+                    .modified()
+                    .map_err(|e| anyhow!("fs stat error for {path:?}: {e}"))?;
+                objects.push(StoredObject {
+                    key,
+                    last_modified: DateTime::<Utc>::from(modified),
+                });
+            }
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(ObjectPage {
+            objects,
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> FsStore {
+        let dir = std::env::temp_dir().join(format!("paste-fs-store-{:x}", rand::random::<u64>()));
+        FsStore::new(dir)
+    }
+
+    #[tokio::test]
+    async fn put_get_delete_roundtrip() {
+        let store = temp_store();
+        store.put("abcde", b"blob bytes".to_vec()).await.unwrap();
+        assert_eq!(store.get("abcde").await.unwrap(), b"blob bytes");
+        store.delete("abcde").await.unwrap();
+        assert!(store.get("abcde").await.unwrap_err().is::<ObjectNotFound>());
+        let _ = std::fs::remove_dir_all(&store.root);
+    }
+
+    #[tokio::test]
+    async fn put_overwrites_existing() {
+        let store = temp_store();
+        store.put("k", b"one".to_vec()).await.unwrap();
+        store.put("k", b"two".to_vec()).await.unwrap();
+        assert_eq!(store.get("k").await.unwrap(), b"two");
+        let _ = std::fs::remove_dir_all(&store.root);
+    }
+
+    #[tokio::test]
+    async fn delete_missing_is_ok() {
+        let store = temp_store();
+        assert!(store.delete("nope").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn list_walks_subdirectories() {
+        let store = temp_store();
+        assert!(store.list(None).await.unwrap().objects.is_empty());
+        store.put("top", b"1".to_vec()).await.unwrap();
+        store.put("nested/key", b"2".to_vec()).await.unwrap();
+        let page = store.list(None).await.unwrap();
+        let keys: Vec<&str> = page.objects.iter().map(|o| o.key.as_str()).collect();
+        assert_eq!(keys, vec!["nested/key", "top"]);
+        assert!(page.next.is_none());
+        let _ = std::fs::remove_dir_all(&store.root);
+    }
+
+    #[tokio::test]
+    async fn writes_use_unique_temp_files_hidden_from_list() {
+        let store = temp_store();
+        let tmp = temp_path(&store.path_for("a.b").unwrap());
+        assert_ne!(tmp, temp_path(&store.path_for("a.b").unwrap()));
+        assert_ne!(tmp, temp_path(&store.path_for("a.c").unwrap()));
+        assert!(is_temp(tmp.file_name().unwrap().to_str().unwrap()));
+        assert!(!is_temp("a.b"));
+
+        let (one, two) = tokio::join!(
+            store.put("k", b"one".to_vec()),
+            store.put("k", b"two".to_vec())
+        );
+        one.unwrap();
+        two.unwrap();
+        assert!(matches!(
+            store.get("k").await.unwrap().as_slice(),
+            b"one" | b"two"
+        ));
+        std::fs::write(&tmp, b"partial").unwrap();
+        let page = store.list(None).await.unwrap();
+        let keys: Vec<&str> = page.objects.iter().map(|o| o.key.as_str()).collect();
+        assert_eq!(keys, vec!["k"]);
+        let _ = std::fs::remove_dir_all(&store.root);
+    }
+
+    #[test]
+    fn rejects_escaping_keys() {
+        let store = FsStore::new("/tmp/paste-root");
+        assert!(store.path_for("../etc/passwd").is_err());
+        assert!(store.path_for("/etc/passwd").is_err());
+        assert!(store.path_for("").is_err());
+        assert!(store.path_for("abc").is_ok());
+    }
+}
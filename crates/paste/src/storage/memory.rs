@@ -0,0 +1,97 @@
+//! In-memory [`BlobStore`] backend.
+//!
+//! Blobs live only as long as the process, so this is intended for tests and
+//! throwaway local runs.  Listings come back in a single page.
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::{BlobStore, ObjectNotFound, ObjectPage, StoredObject};
+
+struct Object {
+    data: Vec<u8>,
+    last_modified: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    objects: Mutex<HashMap<String, Object>>,
+}
+
+impl MemoryStore {
+    fn objects(&self) -> std::sync::MutexGuard<'_, HashMap<String, Object>> {
+        self.objects.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for MemoryStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        let object = Object {
+            data,
+            last_modified: Utc::now(),
+        };
+        self.objects().insert(key.to_string(), object);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        self.objects()
+            .get(key)
+            .map(|object| object.data.clone())
+            .ok_or_else(|| ObjectNotFound(key.to_string()).into())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.objects().remove(key);
+        Ok(())
+    }
+
+    async fn list(&self, _continuation: Option<String>) -> anyhow::Result<ObjectPage> {
+        let mut objects: Vec<StoredObject> = self
+            .objects()
+            .iter()
+            .map(|(key, object)| StoredObject {
+                key: key.clone(),
+                last_modified: object.last_modified,
+            })
+            .collect();
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(ObjectPage {
+            objects,
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn put_get_delete_roundtrip() {
+        let store = MemoryStore::default();
+        store.put("abc", b"blob".to_vec()).await.unwrap();
+        assert_eq!(store.get("abc").await.unwrap(), b"blob");
+        store.delete("abc").await.unwrap();
+        assert!(store.get("abc").await.unwrap_err().is::<ObjectNotFound>());
+    }
+
+    #[tokio::test]
+    async fn delete_missing_is_ok() {
+        let store = MemoryStore::default();
+        assert!(store.delete("nope").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn list_returns_every_key() {
+        let store = MemoryStore::default();
+        store.put("b", b"2".to_vec()).await.unwrap();
+        store.put("a", b"1".to_vec()).await.unwrap();
+        let page = store.list(None).await.unwrap();
+        let keys: Vec<&str> = page.objects.iter().map(|o| o.key.as_str()).collect();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert!(page.next.is_none());
+    }
+}
@@ -0,0 +1,122 @@
+//! S3 / Tigris [`BlobStore`] backend.
+
+use anyhow::anyhow;
+use aws_sdk_s3::primitives::ByteStream;
+use chrono::{DateTime, Utc};
+
+use super::{BlobStore, ObjectNotFound, ObjectPage, StoredObject};
+
+/// Stores blobs as objects in a single S3 bucket.
+pub struct S3Store {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+impl S3Store {
+    pub fn new(client: aws_sdk_s3::Client, bucket: &str) -> Self {
+        Self {
+            client,
+            bucket: bucket.to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for S3Store {
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(ByteStream::from(data))
+            .send()
+            .await
+            .map_err(|e| anyhow!("S3 put_object error for key {key:?}: {e}"))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        let resp = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.as_service_error().is_some_and(|e| e.is_no_such_key()) {
+                    ObjectNotFound(key.to_string()).into()
+                } else {
+                    anyhow!("S3 get_object error for key {key:?}: {e}")
+                }
+            })?;
+        let bytes = resp
+            .body
+            .collect()
+            .await
+            .map_err(|e| anyhow!("S3 read body error for key {key:?}: {e}"))?
+            .into_bytes();
+        Ok(bytes.to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let result = self
+            .client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(()),
+            // Standard S3 answers 204 for a missing key, but Tigris and
+            // other non-standard stores can answer 404.
+            Err(e) if e.raw_response().is_some_and(|r| r.status().as_u16() == 404) => Ok(()),
+            Err(e) => Err(anyhow!("S3 delete_object error for key {key:?}: {e}")),
+        }
+    }
+
+    async fn list(&self, continuation: Option<String>) -> anyhow::Result<ObjectPage> {
+        let resp = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .set_continuation_token(continuation)
+            .send()
+            .await
+            .map_err(|e| anyhow!("S3 list_objects_v2 error: {e}"))?;
+        let objects = resp
+            .contents()
+            .iter()
+            .filter_map(|object| {
+                let modified = object.last_modified()?;
+                Some(StoredObject {
+                    key: object.key()?.to_string(),
+                    last_modified: DateTime::<Utc>::from_timestamp(
+                        modified.secs(),
+                        modified.subsec_nanos(),
+                    )?,
+                })
+            })
+            .collect();
+        Ok(ObjectPage {
+            objects,
+            next: resp.next_continuation_token().map(str::to_string),
+        })
+    }
+}
+
+/// Build an S3 client pointed at the given custom endpoint.
+///
+/// Credentials are read from the standard AWS environment variables
+/// (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`).
+pub async fn create_client(endpoint_url: &str, region: &str) -> aws_sdk_s3::Client {
+    let sdk_config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12())
+        .endpoint_url(endpoint_url)
+        .load()
+        .await;
+    let s3_config = aws_sdk_s3::config::Builder::from(&sdk_config)
+        .region(aws_sdk_s3::config::Region::new(region.to_owned()))
+        .build();
+    aws_sdk_s3::Client::from_conf(s3_config)
+}
@@ -0,0 +1,59 @@
+//! [`BlobStore`] wrapper recording operation latency in
+//! [`crate::metrics::METRICS`].
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::{BlobStore, ObjectPage};
+use crate::metrics::METRICS;
+
+pub struct TimedStore {
+    inner: Arc<dyn BlobStore>,
+    /// `backend` label value, e.g. `"s3"`.
+    backend: &'static str,
+}
+
+impl TimedStore {
+    pub fn new(inner: Arc<dyn BlobStore>, backend: &'static str) -> Self {
+        Self { inner, backend }
+    }
+
+    fn observe<T>(&self, op: &str, started: Instant, result: &anyhow::Result<T>) {
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        METRICS
+            .storage_seconds
+            .with_label_values(&[self.backend, op, outcome])
+            .observe(started.elapsed().as_secs_f64());
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for TimedStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        let started = Instant::now();
+        let result = self.inner.put(key, data).await;
+        self.observe("put", started, &result);
+        result
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        let started = Instant::now();
+        let result = self.inner.get(key).await;
+        self.observe("get", started, &result);
+        result
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let started = Instant::now();
+        let result = self.inner.delete(key).await;
+        self.observe("delete", started, &result);
+        result
+    }
+
+    async fn list(&self, continuation: Option<String>) -> anyhow::Result<ObjectPage> {
+        let started = Instant::now();
+        let result = self.inner.list(continuation).await;
+        self.observe("list", started, &result);
+        result
+    }
+}
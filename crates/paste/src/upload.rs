@@ -0,0 +1,238 @@
+//! Form uploads for `POST /new`.
+//!
+//! Besides a raw body, [`crate::handlers::new_paste`] takes
+//! `multipart/form-data`, for `curl -F`, and
+//! `application/x-www-form-urlencoded`, for HTML forms.  The paste is either
+//! a `content` field or one or more `file` parts, which keep their file
+//! names and make a multi-file paste.  The options otherwise given as query
+//! parameters, and the encryption key, can be sent as fields too.
+//!
+//! curl labels `--data-binary` bodies as urlencoded, so a urlencoded body
+//! without a `content` field is taken to be raw content.
+
+use axum::{
+    body::Bytes,
+    extract::{multipart::Field, FromRequest, Multipart, Request},
+    http::{header, StatusCode},
+    Json,
+};
+use serde_json::json;
+
+use crate::handlers::NewPasteQueryParams;
+
+type Rejection = (StatusCode, Json<serde_json::Value>);
+
+fn bad_request(msg: impl Into<String>) -> Rejection {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({ "error": msg.into() })),
+    )
+}
+
+/// The body of `POST /new`.
+pub enum NewPasteBody {
+    Raw(Bytes),
+    Form(NewPasteForm),
+}
+
+/// A paste uploaded as a form.
+#[derive(Debug, Default)]
+pub struct NewPasteForm {
+    /// The `content` field.
+    pub content: Option<Vec<u8>>,
+    /// `file` parts, in upload order.
+    pub files: Vec<FormFile>,
+    /// Options sent as fields; they override the query string's.
+    pub params: NewPasteQueryParams,
+    pub encryption_key: Option<String>,
+}
+
+/// A `file` part of a multipart upload.
+#[derive(Debug)]
+pub struct FormFile {
+    pub name: String,
+    pub content: Vec<u8>,
+    /// The part's `Content-Type`, unless it's curl's
+    /// `application/octet-stream` default, which says nothing.
+    pub mime_type: Option<String>,
+}
+
+impl NewPasteForm {
+    /// Size of the content and every file.
+    pub fn total_bytes(&self) -> usize {
+        self.content.as_ref().map_or(0, Vec::len)
+            + self.files.iter().map(|f| f.content.len()).sum::<usize>()
+    }
+
+    /// Record the text field `name`.  HTML forms send every input, so blank
+    /// option fields count as missing; unknown fields are ignored.
+    fn set(&mut self, name: &str, value: String) -> Result<(), String> {
+        if name == "content" {
+            self.content = Some(value.into_bytes());
+            return Ok(());
+        }
+        let trimmed = value.trim();
+        if trimmed.is_empty() {
+            return Ok(());
+        }
+        match name {
+            "type" => self.params.type_ = Some(trimmed.to_string()),
+            "ttl_seconds" => {
+                let ttl = trimmed
+                    .parse()
+                    .map_err(|_| "ttl_seconds must be a whole number of seconds")?;
+                self.params.ttl_seconds = Some(ttl);
+            }
+            "burn_after_reading" => {
+                let burn = parse_flag(trimmed).ok_or("burn_after_reading must be true or false")?;
+                self.params.burn_after_reading = Some(burn);
+            }
+            "max_views" => {
+                let max_views = trimmed
+                    .parse()
+                    .map_err(|_| "max_views must be a positive integer")?;
+                self.params.max_views = Some(max_views);
+            }
+            "burn_after_key_failures" => {
+                let failures = trimmed
+                    .parse()
+                    .map_err(|_| "burn_after_key_failures must be a positive integer")?;
+                self.params.burn_after_key_failures = Some(failures);
+            }
+            // Not trimmed: spaces may be part of a password.
+            "encryption_key" => self.encryption_key = Some(value),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Parse a urlencoded body.  `None` if it has no `content` field.
+    fn from_urlencoded(body: &[u8]) -> Result<Option<Self>, String> {
+        let Ok(fields) = serde_urlencoded::from_bytes::<Vec<(String, String)>>(body) else {
+            return Ok(None);
+        };
+        if !fields.iter().any(|(name, _)| name == "content") {
+            return Ok(None);
+        }
+        let mut form = Self::default();
+        for (name, value) in fields {
+            form.set(&name, value)?;
+        }
+        Ok(Some(form))
+    }
+
+    async fn from_multipart(mut multipart: Multipart) -> Result<Self, Rejection> {
+        let mut form = Self::default();
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| (e.status(), Json(json!({ "error": e.body_text() }))))?
+        {
+            let name = field.name().unwrap_or_default().to_string();
+            match (name.as_str(), field.file_name().map(str::to_string)) {
+                ("file", Some(file_name)) => {
+                    let mime_type = field
+                        .content_type()
+                        .filter(|t| !t.starts_with("application/octet-stream"))
+                        .map(str::to_string);
+                    form.files.push(FormFile {
+                        name: file_name,
+                        content: field_bytes(field).await?,
+                        mime_type,
+                    });
+                }
+                ("file" | "content", _) => form.content = Some(field_bytes(field).await?),
+                _ => {
+                    let value = field
+                        .text()
+                        .await
+                        .map_err(|_| bad_request(format!("invalid {name} field")))?;
+                    form.set(&name, value).map_err(bad_request)?;
+                }
+            }
+        }
+        Ok(form)
+    }
+}
+
+async fn field_bytes(field: Field<'_>) -> Result<Vec<u8>, Rejection> {
+    field
+        .bytes()
+        .await
+        .map(|b| b.to_vec())
+        .map_err(|e| (e.status(), Json(json!({ "error": e.body_text() }))))
+}
+
+/// A boolean field.  HTML checkboxes send `on`.
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "on" | "yes" | "1" => Some(true),
+        "false" | "off" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+impl<S: Send + Sync> FromRequest<S> for NewPasteBody {
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        if content_type.starts_with("multipart/form-data") {
+            let multipart = Multipart::from_request(req, state)
+                .await
+                .map_err(|e| (e.status(), Json(json!({ "error": e.body_text() }))))?;
+            return NewPasteForm::from_multipart(multipart)
+                .await
+                .map(Self::Form);
+        }
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| (e.status(), Json(json!({ "error": e.body_text() }))))?;
+        if content_type.starts_with("application/x-www-form-urlencoded") {
+            if let Some(form) = NewPasteForm::from_urlencoded(&body).map_err(bad_request)? {
+                return Ok(Self::Form(form));
+            }
+        }
+        Ok(Self::Raw(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urlencoded_form_fields() {
+        let form = NewPasteForm::from_urlencoded(
+            b"content=fn+main()+%7B%7D&type=rust&ttl_seconds=&burn_after_reading=on&encryption_key=",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(form.content.as_deref(), Some(&b"fn main() {}"[..]));
+        assert_eq!(form.params.type_.as_deref(), Some("rust"));
+        assert_eq!(form.params.ttl_seconds, None);
+        assert_eq!(form.params.burn_after_reading, Some(true));
+        assert_eq!(form.encryption_key, None);
+    }
+
+    #[test]
+    fn urlencoded_without_content_is_raw() {
+        assert!(NewPasteForm::from_urlencoded(b"just a log line")
+            .unwrap()
+            .is_none());
+        assert!(NewPasteForm::from_urlencoded(b"type=rust&x=1")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn invalid_option_fields_are_errors() {
+        assert!(NewPasteForm::from_urlencoded(b"content=x&max_views=lots").is_err());
+        assert!(NewPasteForm::from_urlencoded(b"content=x&burn_after_reading=maybe").is_err());
+    }
+}
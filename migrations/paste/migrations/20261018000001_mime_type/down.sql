@@ -0,0 +1,2 @@
+ALTER TABLE pastes
+    DROP COLUMN mime_type;
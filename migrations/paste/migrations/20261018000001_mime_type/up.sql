@@ -0,0 +1,4 @@
+-- Record the MIME type of the uploaded bytes so /raw/{key} can serve binary
+-- pastes back unchanged.  NULL (pre-existing rows) means UTF-8 plain text.
+ALTER TABLE pastes
+    ADD COLUMN mime_type TEXT;
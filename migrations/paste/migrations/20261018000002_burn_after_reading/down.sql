@@ -0,0 +1,3 @@
+ALTER TABLE pastes
+    DROP COLUMN burn_after_reading,
+    DROP COLUMN date_burned;
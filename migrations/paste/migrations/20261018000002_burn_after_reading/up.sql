@@ -0,0 +1,6 @@
+-- Burn-after-reading pastes are deleted after their first successful read.
+-- date_burned is stamped by the reader that claims the paste; once set the
+-- paste is no longer readable and the sweeper queues it for deletion.
+ALTER TABLE pastes
+    ADD COLUMN burn_after_reading BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN date_burned TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+ALTER TABLE pastes
+    DROP COLUMN view_count,
+    DROP COLUMN max_views;
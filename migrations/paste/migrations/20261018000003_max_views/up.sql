@@ -0,0 +1,6 @@
+-- view_count is incremented by every successful read.  When max_views is set
+-- the paste stops being readable once view_count reaches it and is queued for
+-- deletion.
+ALTER TABLE pastes
+    ADD COLUMN view_count INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN max_views INTEGER;
@@ -0,0 +1,5 @@
+-- HMAC of the secret deletion token handed to the creator by POST /new.
+-- NULL for pastes created before deletion tokens existed; those can't be
+-- deleted by their owner.
+ALTER TABLE pastes
+    ADD COLUMN deletion_token_hash TEXT;
@@ -0,0 +1,5 @@
+ALTER TABLE pastes
+    DROP COLUMN current_rev,
+    DROP COLUMN edit_token_hash;
+
+DROP TABLE paste_revisions;
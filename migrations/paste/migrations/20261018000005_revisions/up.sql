@@ -0,0 +1,22 @@
+-- Every paste has one or more revisions, each its own encrypted blob.
+-- Revision 1 is the content the paste was created with; pastes.storage_uri,
+-- content_type and mime_type always mirror the latest revision
+-- (pastes.current_rev).
+CREATE TABLE paste_revisions (
+    paste_id INTEGER NOT NULL REFERENCES pastes (id) ON DELETE CASCADE,
+    rev INTEGER NOT NULL,
+    storage_uri TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    mime_type TEXT,
+    date_created TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (paste_id, rev)
+);
+
+INSERT INTO paste_revisions (paste_id, rev, storage_uri, content_type, mime_type, date_created)
+SELECT id, 1, storage_uri, content_type, mime_type, date_created FROM pastes;
+
+-- HMAC of the secret edit token handed to the creator by POST /new.  NULL
+-- for pastes that can't be edited.
+ALTER TABLE pastes
+    ADD COLUMN current_rev INTEGER NOT NULL DEFAULT 1,
+    ADD COLUMN edit_token_hash TEXT;
@@ -0,0 +1,3 @@
+-- The paste this one was forked from.  Deleting the parent keeps the fork.
+ALTER TABLE pastes
+    ADD COLUMN parent_id INTEGER REFERENCES pastes (id) ON DELETE SET NULL;
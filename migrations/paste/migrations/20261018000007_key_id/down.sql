@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS paste_revisions_key_id_idx;
+ALTER TABLE paste_revisions DROP COLUMN key_id;
@@ -0,0 +1,5 @@
+-- Server key each revision's blob is encrypted under, so the re-encryption
+-- job can find blobs still on a retired key.  NULL = not yet recorded;
+-- ':user' = encrypted with a user-supplied key (never re-encrypted).
+ALTER TABLE paste_revisions ADD COLUMN key_id TEXT;
+CREATE INDEX paste_revisions_key_id_idx ON paste_revisions (key_id);
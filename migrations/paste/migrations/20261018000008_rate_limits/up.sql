@@ -0,0 +1,11 @@
+-- Token buckets for per-client rate limits on paste creation, shared by
+-- every instance.  `tokens` is the level at `updated_at`; refill since then
+-- is computed on each take.
+CREATE TABLE paste_rate_limits (
+    client TEXT NOT NULL,
+    bucket TEXT NOT NULL,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp(),
+    PRIMARY KEY (client, bucket)
+);
+CREATE INDEX paste_rate_limits_updated_at_idx ON paste_rate_limits (updated_at);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS pastes_owner_id_idx;
+ALTER TABLE pastes DROP COLUMN owner_id;
+DROP TABLE paste_api_tokens;
+DROP TABLE paste_users;
@@ -0,0 +1,20 @@
+-- Optional accounts for paste creators.  API clients authenticate with
+-- bearer tokens, stored as their HMAC like the pastes' owner tokens.
+CREATE TABLE paste_users (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    date_created TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE paste_api_tokens (
+    id SERIAL PRIMARY KEY,
+    hash TEXT UNIQUE NOT NULL,
+    user_id INT NOT NULL REFERENCES paste_users(id) ON DELETE CASCADE,
+    name TEXT,
+    date_created TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    date_used TIMESTAMPTZ
+);
+CREATE INDEX paste_api_tokens_user_id_idx ON paste_api_tokens (user_id);
+
+ALTER TABLE pastes ADD COLUMN owner_id INT REFERENCES paste_users(id) ON DELETE SET NULL;
+CREATE INDEX pastes_owner_id_idx ON pastes (owner_id);
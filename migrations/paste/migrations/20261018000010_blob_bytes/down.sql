@@ -0,0 +1 @@
+ALTER TABLE paste_revisions DROP COLUMN blob_bytes;
@@ -0,0 +1,3 @@
+-- Size of each revision's stored blob, for the bytes-stored metric.  NULL
+-- for blobs written before this was recorded.
+ALTER TABLE paste_revisions ADD COLUMN blob_bytes BIGINT;
@@ -0,0 +1,2 @@
+DROP TABLE paste_deletion_dead_letters;
+DROP TABLE paste_deletion_jobs;
@@ -0,0 +1,29 @@
+-- Durable deletion queue, replacing the in-process channel.  Workers on
+-- every instance claim due jobs with FOR UPDATE SKIP LOCKED; a failed
+-- attempt is retried after an exponential backoff, and a job that keeps
+-- failing moves to paste_deletion_dead_letters.  No foreign key: the job
+-- outlives the paste row it deletes.
+CREATE TABLE paste_deletion_jobs (
+    paste_id INT PRIMARY KEY,
+    storage_uri TEXT NOT NULL,
+    paste_created TIMESTAMPTZ NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    run_after TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    date_queued TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX paste_deletion_jobs_run_after_idx ON paste_deletion_jobs (run_after);
+
+CREATE TABLE paste_deletion_dead_letters (
+    paste_id INT PRIMARY KEY,
+    storage_uri TEXT NOT NULL,
+    paste_created TIMESTAMPTZ NOT NULL,
+    attempts INT NOT NULL,
+    last_error TEXT,
+    date_queued TIMESTAMPTZ NOT NULL,
+    date_failed TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Pastes stamped as queued may have been lost with the old channel.
+INSERT INTO paste_deletion_jobs (paste_id, storage_uri, paste_created, date_queued)
+SELECT id, storage_uri, date_created, date_queued FROM pastes WHERE date_queued IS NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE paste_revisions DROP COLUMN date_blob_missing;
@@ -0,0 +1,3 @@
+-- Set by the storage reconciliation job when a revision's blob isn't in the
+-- blob store; cleared if it turns up again.
+ALTER TABLE paste_revisions ADD COLUMN date_blob_missing TIMESTAMPTZ;
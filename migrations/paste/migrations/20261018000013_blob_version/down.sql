@@ -0,0 +1,2 @@
+ALTER TABLE paste_revisions DROP COLUMN content_bytes;
+ALTER TABLE paste_revisions DROP COLUMN blob_version;
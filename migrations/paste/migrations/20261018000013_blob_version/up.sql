@@ -0,0 +1,6 @@
+-- Header version of each revision's blob and the size of its content, so
+-- paste metadata can be served without fetching the blob.  NULL for blobs
+-- written before these were recorded; the re-encryption job fills in
+-- blob_version.
+ALTER TABLE paste_revisions ADD COLUMN blob_version INTEGER;
+ALTER TABLE paste_revisions ADD COLUMN content_bytes BIGINT;
@@ -0,0 +1,3 @@
+ALTER TABLE pastes DROP COLUMN burn_after_key_failures;
+ALTER TABLE pastes DROP COLUMN date_key_failed;
+ALTER TABLE pastes DROP COLUMN key_failures;
@@ -0,0 +1,6 @@
+-- Wrong user keys given for each paste since its last successful read, and
+-- when the last one was, for brute-force protection.
+ALTER TABLE pastes ADD COLUMN key_failures INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE pastes ADD COLUMN date_key_failed TIMESTAMPTZ;
+-- Burn the paste once key_failures reaches this; opted into at creation.
+ALTER TABLE pastes ADD COLUMN burn_after_key_failures INTEGER;
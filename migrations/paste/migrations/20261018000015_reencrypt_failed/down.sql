@@ -0,0 +1 @@
+ALTER TABLE paste_revisions DROP COLUMN date_reencrypt_failed;
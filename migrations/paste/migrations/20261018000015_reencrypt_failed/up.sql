@@ -0,0 +1,3 @@
+-- Set by the re-encryption job when a revision's blob can't be fetched or
+-- decrypted, so it is retried after a back-off rather than on every pass.
+ALTER TABLE paste_revisions ADD COLUMN date_reencrypt_failed TIMESTAMPTZ;